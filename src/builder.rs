@@ -0,0 +1,66 @@
+use crate::{Value, ValueMap};
+
+/// Builds a `Value::Map` one entry at a time. See `Value::map_builder`.
+#[derive(Clone, Debug, Default)]
+pub struct MapBuilder {
+    map: ValueMap,
+}
+
+impl MapBuilder {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Inserts an entry, replacing any earlier value for the same key.
+    pub fn entry<K: Into<Value>, V: Into<Value>>(mut self, key: K, value: V) -> Self {
+        self.map.insert(key.into(), value.into());
+        self
+    }
+
+    pub fn build(self) -> Value {
+        Value::Map(self.map)
+    }
+}
+
+/// Builds a `Value::Seq` one element at a time. See `Value::seq_builder`.
+#[derive(Clone, Debug, Default)]
+pub struct SeqBuilder {
+    seq: Vec<Value>,
+}
+
+impl SeqBuilder {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn push<V: Into<Value>>(mut self, value: V) -> Self {
+        self.seq.push(value.into());
+        self
+    }
+
+    pub fn build(self) -> Value {
+        Value::Seq(self.seq)
+    }
+}
+
+impl From<MapBuilder> for Value {
+    fn from(builder: MapBuilder) -> Value {
+        builder.build()
+    }
+}
+
+impl From<SeqBuilder> for Value {
+    fn from(builder: SeqBuilder) -> Value {
+        builder.build()
+    }
+}
+
+impl Value {
+    pub fn map_builder() -> MapBuilder {
+        MapBuilder::new()
+    }
+
+    pub fn seq_builder() -> SeqBuilder {
+        SeqBuilder::new()
+    }
+}
@@ -0,0 +1,73 @@
+use crate::Value;
+
+impl Value {
+    /// Concatenates a seq of `Bytes` into a single `Bytes`, or returns
+    /// `None` if `self` is not a seq or any element is not `Bytes`.
+    pub fn concat_bytes(&self) -> Option<Value> {
+        self.concat_bytes_with(|_, _| ())
+    }
+
+    /// Like `concat_bytes`, but prefixes each chunk with its length so that
+    /// `split_bytes_with_lengths` can recover the original seq.
+    ///
+    /// Lengths are unsigned LEB128 varints: seven bits per byte, least
+    /// significant group first, with the high bit set on every byte except
+    /// the last.
+    pub fn concat_bytes_with_lengths(&self) -> Option<Value> {
+        self.concat_bytes_with(|buf, mut len| {
+            while len >= 0x80 {
+                buf.push(len as u8 | 0x80);
+                len >>= 7;
+            }
+            buf.push(len as u8);
+        })
+    }
+
+    fn concat_bytes_with<F: FnMut(&mut Vec<u8>, usize)>(&self, mut prefix: F) -> Option<Value> {
+        let mut buf = Vec::new();
+        match *self {
+            Value::Seq(ref seq) => for v in seq {
+                match *v {
+                    Value::Bytes(ref v) => {
+                        prefix(&mut buf, v.len());
+                        buf.extend_from_slice(v);
+                    },
+                    _ => return None,
+                }
+            },
+            _ => return None,
+        }
+        Some(Value::Bytes(buf))
+    }
+
+    /// Splits `Bytes` produced by `concat_bytes_with_lengths` back into a
+    /// seq of `Bytes`, or returns `None` if `self` is not `Bytes` or is
+    /// truncated.
+    pub fn split_bytes_with_lengths(&self) -> Option<Value> {
+        let mut bytes = match *self {
+            Value::Bytes(ref v) => &v[..],
+            _ => return None,
+        };
+        let mut seq = Vec::new();
+        while !bytes.is_empty() {
+            let mut len = 0usize;
+            let mut shift = 0;
+            loop {
+                let (&b, rest) = bytes.split_first()?;
+                bytes = rest;
+                len |= ((b & 0x7f) as usize).checked_shl(shift)?;
+                shift += 7;
+                if b & 0x80 == 0 {
+                    break
+                }
+            }
+            if len > bytes.len() {
+                return None
+            }
+            let (chunk, rest) = bytes.split_at(len);
+            seq.push(Value::Bytes(chunk.to_vec()));
+            bytes = rest;
+        }
+        Some(Value::Seq(seq))
+    }
+}
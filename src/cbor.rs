@@ -0,0 +1,109 @@
+//! Conversions between `Value` and `ciborium::value::Value`.
+//!
+//! CBOR integers are converted to the narrowest fitting unsigned variant, or
+//! the narrowest signed variant when negative. Negative integers below
+//! `i64::MIN` cannot be represented, so converting them is an error.
+//!
+//! Bignums (tags 2 and 3 on a byte string) become `Value::BigInt`. Other
+//! CBOR tags are not preserved: the tagged item is wrapped in a
+//! `Value::Newtype` and the tag number is discarded. CBOR null becomes
+//! `Value::Unit`.
+//!
+//! Every `Value` has a CBOR representation, so the conversion back is
+//! infallible and `TryFrom` is provided by the blanket impl over `From`.
+//! `Value::Option` and `Value::Newtype` wrappers are unwrapped, with
+//! `Value::Option(None)` and `Value::Unit` both becoming null, and
+//! `Value::Char` becomes a text string.
+
+use std::convert::{TryFrom, TryInto};
+use ciborium::value::{Integer, Value as CborValue};
+use serde::de;
+
+use crate::{DeserializerError, Value};
+use crate::de::{narrow_signed, narrow_unsigned};
+
+impl TryFrom<CborValue> for Value {
+    type Error = DeserializerError;
+
+    fn try_from(value: CborValue) -> Result<Value, DeserializerError> {
+        Ok(match value {
+            CborValue::Integer(v) => integer_value(v)?,
+            CborValue::Bytes(v) => Value::Bytes(v),
+            CborValue::Float(v) => Value::F64(v),
+            CborValue::Text(v) => Value::String(v),
+            CborValue::Bool(v) => Value::Bool(v),
+            CborValue::Null => Value::Unit,
+            CborValue::Tag(tag @ 2..=3, v) => match *v {
+                CborValue::Bytes(v) => Value::BigInt(bignum_to_twos_complement(tag == 3, v)),
+                v => Value::Newtype(Box::new(v.try_into()?)),
+            },
+            CborValue::Tag(_, v) => Value::Newtype(Box::new((*v).try_into()?)),
+            CborValue::Array(v) => Value::Seq(v.into_iter().map(Value::try_from).collect::<Result<_, _>>()?),
+            CborValue::Map(v) => Value::Map(v.into_iter()
+                .map(|(k, v)| Ok((k.try_into()?, v.try_into()?)))
+                .collect::<Result<_, DeserializerError>>()?),
+            // the enum is non-exhaustive, future kinds of value have no equivalent
+            v => return Err(de::Error::custom(format_args!("unsupported CBOR value {:?}", v))),
+        })
+    }
+}
+
+fn integer_value(v: Integer) -> Result<Value, DeserializerError> {
+    let v = i128::from(v);
+    if let Ok(v) = u64::try_from(v) {
+        Ok(narrow_unsigned(v))
+    } else if let Ok(v) = i64::try_from(v) {
+        Ok(narrow_signed(v))
+    } else {
+        Err(de::Error::custom(format_args!("integer {} out of range for Value", v)))
+    }
+}
+
+// a bignum magnitude n stands for n, or for -1 - n when negative, which in
+// two's complement is n with its bits inverted
+fn bignum_to_twos_complement(negative: bool, mut v: Vec<u8>) -> Vec<u8> {
+    // an empty magnitude still needs a byte for the sign when it means -1
+    if v.first().is_some_and(|b| b & 0x80 != 0) || (negative && v.is_empty()) {
+        v.insert(0, 0);
+    }
+    if negative {
+        v.iter_mut().for_each(|b| *b = !*b);
+    }
+    v
+}
+
+fn twos_complement_to_bignum(mut v: Vec<u8>) -> CborValue {
+    let negative = v.first().is_some_and(|b| b & 0x80 != 0);
+    if negative {
+        v.iter_mut().for_each(|b| *b = !*b);
+    }
+    let leading_zeros = v.iter().take_while(|&&b| b == 0).count();
+    v.drain(..leading_zeros);
+    CborValue::Tag(if negative { 3 } else { 2 }, Box::new(CborValue::Bytes(v)))
+}
+
+impl From<Value> for CborValue {
+    fn from(value: Value) -> CborValue {
+        match value {
+            Value::Bool(v) => CborValue::Bool(v),
+            Value::U8(v) => CborValue::Integer(v.into()),
+            Value::U16(v) => CborValue::Integer(v.into()),
+            Value::U32(v) => CborValue::Integer(v.into()),
+            Value::U64(v) => CborValue::Integer(v.into()),
+            Value::I8(v) => CborValue::Integer(v.into()),
+            Value::I16(v) => CborValue::Integer(v.into()),
+            Value::I32(v) => CborValue::Integer(v.into()),
+            Value::I64(v) => CborValue::Integer(v.into()),
+            Value::F32(v) => CborValue::Float(v.into()),
+            Value::F64(v) => CborValue::Float(v),
+            Value::Char(v) => CborValue::Text(v.to_string()),
+            Value::String(v) => CborValue::Text(v),
+            Value::Unit | Value::Option(None) => CborValue::Null,
+            Value::Option(Some(v)) | Value::Newtype(v) => (*v).into(),
+            Value::Seq(v) => CborValue::Array(v.into_iter().map(CborValue::from).collect()),
+            Value::Map(v) => CborValue::Map(v.into_iter().map(|(k, v)| (k.into(), v.into())).collect()),
+            Value::Bytes(v) => CborValue::Bytes(v),
+            Value::BigInt(v) => twos_complement_to_bignum(v),
+        }
+    }
+}
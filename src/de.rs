@@ -1,10 +1,15 @@
 use serde::{forward_to_deserialize_any, de};
-use std::collections::BTreeMap;
+use std::borrow::{Borrow, Cow};
+use std::convert::TryFrom;
 use std::error::Error;
 use std::fmt;
+use std::iter;
 use std::marker::PhantomData;
+use std::mem;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
 
-use crate::Value;
+use crate::{map, map_value, Value, ValueMap, BIGINT_TOKEN};
 
 #[derive(Debug)]
 pub enum Unexpected {
@@ -88,7 +93,11 @@ pub enum DeserializerError {
     UnknownVariant(String, &'static [&'static str]),
     UnknownField(String, &'static [&'static str]),
     MissingField(&'static str),
+    /// A map has no entry for a key only known at runtime, as looked up by
+    /// `Value::try_get`.
+    MissingKey(String),
     DuplicateField(&'static str),
+    Multiple(Vec<DeserializerError>),
 }
 
 impl de::Error for DeserializerError {
@@ -139,7 +148,9 @@ impl DeserializerError {
             DeserializerError::UnknownVariant(ref field, exp) => E::unknown_variant(field, exp),
             DeserializerError::UnknownField(ref field, exp) => E::unknown_field(field, exp),
             DeserializerError::MissingField(field) => E::missing_field(field),
+            DeserializerError::MissingKey(ref key) => E::custom(format_args!("missing key {}", key)),
             DeserializerError::DuplicateField(field) => E::missing_field(field),
+            DeserializerError::Multiple(..) => E::custom(self),
         }
     }
 
@@ -171,12 +182,52 @@ impl fmt::Display for DeserializerError {
                 write!(f, "Unknown variant {}. Expected one of {}", field, exp.join(", "))
             },
             DeserializerError::UnknownField(ref field, exp) => {
-                write!(f, "Unknown field {}. Expected one of {}", field, exp.join(", "))
+                write!(f, "Unknown field {}. Expected one of {}", field, exp.join(", "))?;
+                match closest_field(field, exp) {
+                    Some(suggestion) => write!(f, ". Did you mean {}?", suggestion),
+                    None => Ok(()),
+                }
             }
             DeserializerError::MissingField(field) => write!(f, "Missing field {}", field),
+            DeserializerError::MissingKey(ref key) => write!(f, "Missing key {}", key),
             DeserializerError::DuplicateField(field) => write!(f, "Duplicate field {}", field),
+            DeserializerError::Multiple(ref errors) => {
+                for (i, e) in errors.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str("; ")?;
+                    }
+                    write!(f, "{}", e)?;
+                }
+                Ok(())
+            },
+        }
+    }
+}
+
+// the expected field nearest to `field` by edit distance, if it is close
+// enough to be a plausible typo
+fn closest_field(field: &str, expected: &[&'static str]) -> Option<&'static str> {
+    expected.iter()
+        .map(|&candidate| (edit_distance(field, candidate), candidate))
+        .filter(|&(distance, candidate)| distance <= field.chars().count().max(candidate.chars().count()) / 2)
+        .min_by_key(|&(distance, _)| distance)
+        .map(|(_, candidate)| candidate)
+}
+
+// Levenshtein distance between two strings, counted in chars
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, &cb) in b.iter().enumerate() {
+            let above = row[j + 1];
+            row[j + 1] = (above + 1).min(row[j] + 1).min(diagonal + (ca != cb) as usize);
+            diagonal = above;
         }
     }
+    row[b.len()]
 }
 
 impl From<de::value::Error> for DeserializerError {
@@ -218,116 +269,1316 @@ impl<'de> de::Visitor<'de> for ValueVisitor {
         Ok(Value::U8(value))
     }
 
-    fn visit_u16<E>(self, value: u16) -> Result<Value, E> {
-        Ok(Value::U16(value))
+    fn visit_u16<E>(self, value: u16) -> Result<Value, E> {
+        Ok(Value::U16(value))
+    }
+
+    fn visit_u32<E>(self, value: u32) -> Result<Value, E> {
+        Ok(Value::U32(value))
+    }
+
+    fn visit_u64<E>(self, value: u64) -> Result<Value, E> {
+        Ok(Value::U64(value))
+    }
+
+    fn visit_f32<E>(self, value: f32) -> Result<Value, E> {
+        Ok(Value::F32(value))
+    }
+
+    fn visit_f64<E>(self, value: f64) -> Result<Value, E> {
+        Ok(Value::F64(value))
+    }
+
+    fn visit_char<E>(self, value: char) -> Result<Value, E> {
+        Ok(Value::Char(value))
+    }
+
+    fn visit_str<E>(self, value: &str) -> Result<Value, E> {
+        Ok(Value::String(value.into()))
+    }
+
+    fn visit_string<E>(self, value: String) -> Result<Value, E> {
+        Ok(Value::String(value))
+    }
+
+    fn visit_unit<E>(self) -> Result<Value, E> {
+        Ok(Value::Unit)
+    }
+
+    fn visit_none<E>(self) -> Result<Value, E> {
+        Ok(Value::Option(None))
+    }
+
+    fn visit_some<D: de::Deserializer<'de>>(self, d: D) -> Result<Value, D::Error> {
+        d.deserialize_any(ValueVisitor).map(|v| Value::Option(Some(Box::new(v))))
+    }
+
+    fn visit_newtype_struct<D: de::Deserializer<'de>>(self, d: D) -> Result<Value, D::Error> {
+        d.deserialize_any(ValueVisitor).map(|v| Value::Newtype(Box::new(v)))
+    }
+
+    fn visit_seq<V: de::SeqAccess<'de>>(self, mut visitor: V) -> Result<Value, V::Error> {
+        let mut values = Vec::new();
+        while let Some(elem) = visitor.next_element()? {
+            values.push(elem);
+        }
+        Ok(Value::Seq(values))
+    }
+
+    fn visit_map<V: de::MapAccess<'de>>(self, mut visitor: V) -> Result<Value, V::Error> {
+        let mut values = ValueMap::new();
+        while let Some((key, value)) = visitor.next_entry()? {
+            values.insert(key, value);
+        }
+        Ok(map_value(values))
+    }
+
+    fn visit_bytes<E>(self, v: &[u8]) -> Result<Value, E> {
+        Ok(Value::Bytes(v.into()))
+    }
+
+    fn visit_byte_buf<E>(self, v: Vec<u8>) -> Result<Value, E> {
+        Ok(Value::Bytes(v))
+    }
+}
+
+impl<'de> de::Deserialize<'de> for Value {
+    fn deserialize<D: de::Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        d.deserialize_any(ValueVisitor)
+    }
+}
+
+/// Deserializes a `Value` with control over how it is built.
+///
+/// With the default settings this behaves exactly like `Value::deserialize`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ValueSeed {
+    narrow_integers: bool,
+    reject_non_finite: bool,
+}
+
+impl ValueSeed {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Stores every integer in the narrowest variant of the same signedness
+    /// that can hold it, rather than the variant the format visited.
+    pub fn narrow_integers(mut self, enabled: bool) -> Self {
+        self.narrow_integers = enabled;
+        self
+    }
+
+    /// Fails on any NaN or infinite float instead of storing it, to catch
+    /// bad data from untrusted sources.
+    pub fn reject_non_finite(mut self, enabled: bool) -> Self {
+        self.reject_non_finite = enabled;
+        self
+    }
+
+    fn float<E: de::Error>(self, v: f64, value: Value) -> Result<Value, E> {
+        if self.reject_non_finite && !v.is_finite() {
+            Err(de::Error::invalid_value(de::Unexpected::Float(v), &"a finite number"))
+        } else {
+            Ok(value)
+        }
+    }
+
+    fn unsigned(self, v: u64, value: Value) -> Value {
+        if self.narrow_integers {
+            narrow_unsigned(v)
+        } else {
+            value
+        }
+    }
+
+    fn signed(self, v: i64, value: Value) -> Value {
+        if self.narrow_integers {
+            narrow_signed(v)
+        } else {
+            value
+        }
+    }
+}
+
+pub(crate) fn narrow_unsigned(v: u64) -> Value {
+    if let Ok(v) = u8::try_from(v) {
+        Value::U8(v)
+    } else if let Ok(v) = u16::try_from(v) {
+        Value::U16(v)
+    } else if let Ok(v) = u32::try_from(v) {
+        Value::U32(v)
+    } else {
+        Value::U64(v)
+    }
+}
+
+pub(crate) fn narrow_signed(v: i64) -> Value {
+    if let Ok(v) = i8::try_from(v) {
+        Value::I8(v)
+    } else if let Ok(v) = i16::try_from(v) {
+        Value::I16(v)
+    } else if let Ok(v) = i32::try_from(v) {
+        Value::I32(v)
+    } else {
+        Value::I64(v)
+    }
+}
+
+impl<'de> de::DeserializeSeed<'de> for ValueSeed {
+    type Value = Value;
+
+    fn deserialize<D: de::Deserializer<'de>>(self, d: D) -> Result<Value, D::Error> {
+        d.deserialize_any(self)
+    }
+}
+
+impl<'de> de::Visitor<'de> for ValueSeed {
+    type Value = Value;
+
+    fn expecting(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.write_str("any value")
+    }
+
+    fn visit_bool<E>(self, value: bool) -> Result<Value, E> {
+        Ok(Value::Bool(value))
+    }
+
+    fn visit_i8<E>(self, value: i8) -> Result<Value, E> {
+        Ok(self.signed(value.into(), Value::I8(value)))
+    }
+
+    fn visit_i16<E>(self, value: i16) -> Result<Value, E> {
+        Ok(self.signed(value.into(), Value::I16(value)))
+    }
+
+    fn visit_i32<E>(self, value: i32) -> Result<Value, E> {
+        Ok(self.signed(value.into(), Value::I32(value)))
+    }
+
+    fn visit_i64<E>(self, value: i64) -> Result<Value, E> {
+        Ok(self.signed(value, Value::I64(value)))
+    }
+
+    fn visit_u8<E>(self, value: u8) -> Result<Value, E> {
+        Ok(self.unsigned(value.into(), Value::U8(value)))
+    }
+
+    fn visit_u16<E>(self, value: u16) -> Result<Value, E> {
+        Ok(self.unsigned(value.into(), Value::U16(value)))
+    }
+
+    fn visit_u32<E>(self, value: u32) -> Result<Value, E> {
+        Ok(self.unsigned(value.into(), Value::U32(value)))
+    }
+
+    fn visit_u64<E>(self, value: u64) -> Result<Value, E> {
+        Ok(self.unsigned(value, Value::U64(value)))
+    }
+
+    fn visit_f32<E: de::Error>(self, value: f32) -> Result<Value, E> {
+        self.float(value.into(), Value::F32(value))
+    }
+
+    fn visit_f64<E: de::Error>(self, value: f64) -> Result<Value, E> {
+        self.float(value, Value::F64(value))
+    }
+
+    fn visit_char<E>(self, value: char) -> Result<Value, E> {
+        Ok(Value::Char(value))
+    }
+
+    fn visit_str<E>(self, value: &str) -> Result<Value, E> {
+        Ok(Value::String(value.into()))
+    }
+
+    fn visit_string<E>(self, value: String) -> Result<Value, E> {
+        Ok(Value::String(value))
+    }
+
+    fn visit_unit<E>(self) -> Result<Value, E> {
+        Ok(Value::Unit)
+    }
+
+    fn visit_none<E>(self) -> Result<Value, E> {
+        Ok(Value::Option(None))
+    }
+
+    fn visit_some<D: de::Deserializer<'de>>(self, d: D) -> Result<Value, D::Error> {
+        d.deserialize_any(self).map(|v| Value::Option(Some(Box::new(v))))
+    }
+
+    fn visit_newtype_struct<D: de::Deserializer<'de>>(self, d: D) -> Result<Value, D::Error> {
+        d.deserialize_any(self).map(|v| Value::Newtype(Box::new(v)))
+    }
+
+    fn visit_seq<V: de::SeqAccess<'de>>(self, mut visitor: V) -> Result<Value, V::Error> {
+        let mut values = Vec::new();
+        while let Some(elem) = visitor.next_element_seed(self)? {
+            values.push(elem);
+        }
+        Ok(Value::Seq(values))
+    }
+
+    fn visit_map<V: de::MapAccess<'de>>(self, mut visitor: V) -> Result<Value, V::Error> {
+        let mut values = ValueMap::new();
+        while let Some((key, value)) = visitor.next_entry_seed(self, self)? {
+            values.insert(key, value);
+        }
+        Ok(map_value(values))
+    }
+
+    fn visit_bytes<E>(self, v: &[u8]) -> Result<Value, E> {
+        Ok(Value::Bytes(v.into()))
+    }
+
+    fn visit_byte_buf<E>(self, v: Vec<u8>) -> Result<Value, E> {
+        Ok(Value::Bytes(v))
+    }
+}
+
+impl Value {
+    /// Like `deserialize_into`, but reports every unknown field and the first
+    /// type mismatch of each struct together as `DeserializerError::Multiple`.
+    ///
+    /// See `ValueDeserializer::collect_errors` for the limitations.
+    pub fn deserialize_into_collecting<'de, T: de::Deserialize<'de>>(self) -> Result<T, DeserializerError> {
+        let deserializer = ValueDeserializer::<DeserializerError>::new(self).collect_errors(true);
+        let collected = deserializer.options.collected.clone().expect("collect_errors was enabled");
+        T::deserialize(deserializer).map_err(|e| {
+            let mut collected = collected.lock().unwrap_or_else(|e| e.into_inner());
+            if collected.is_empty() {
+                e
+            } else {
+                DeserializerError::Multiple(mem::take(&mut *collected))
+            }
+        })
+    }
+
+    /// Builds a `Value` from any deserializer, storing integers in the
+    /// narrowest variant that can hold them.
+    ///
+    /// Self-describing formats such as JSON only report 64-bit integers, so
+    /// this produces more compact trees than `Value::deserialize`.
+    pub fn from_deserializer<'de, D: de::Deserializer<'de>>(d: D) -> Result<Value, D::Error> {
+        de::DeserializeSeed::deserialize(ValueSeed::new().narrow_integers(true), d)
+    }
+}
+
+/// A `Value` captured as-is while deserializing a larger structure.
+///
+/// Use it as a field type to defer deserializing part of a tree, then call
+/// `Value::deserialize_into` on the wrapped value once its type is known.
+/// It serializes as the wrapped value, without any newtype wrapper.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct RawValue(pub Value);
+
+impl<'de> de::Deserialize<'de> for RawValue {
+    fn deserialize<D: de::Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        Value::from_deserializer(d).map(RawValue)
+    }
+}
+
+impl serde::Serialize for RawValue {
+    fn serialize<S: serde::Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        self.0.serialize(s)
+    }
+}
+
+impl<'de> de::IntoDeserializer<'de, DeserializerError> for Value {
+    type Deserializer = Value;
+
+    fn into_deserializer(self) -> Value {
+        self
+    }
+}
+
+macro_rules! forward_to_deserialize_newtype_inner {
+    ($($func:ident($($arg:ident: $ty:ty),*))*) => {
+        $(
+            fn $func<V: de::Visitor<'de>>(self, $($arg: $ty,)* visitor: V) -> Result<V::Value, Self::Error> {
+                match self.newtype_inner() {
+                    Ok(inner) => inner?.$func($($arg,)* visitor),
+                    Err(de) => de.deserialize_any(visitor),
+                }
+            }
+        )*
+    };
+}
+
+// converts a value to a requested numeric type when no precision is lost
+trait NumberFromValue: Sized {
+    fn from_value(value: &Value) -> Option<Self>;
+}
+
+macro_rules! impl_integer_from_value {
+    ($($ty:ty)*) => {
+        $(
+            impl NumberFromValue for $ty {
+                fn from_value(value: &Value) -> Option<Self> {
+                    match *value {
+                        Value::U8(v) => Self::try_from(v as u64).ok(),
+                        Value::U16(v) => Self::try_from(v as u64).ok(),
+                        Value::U32(v) => Self::try_from(v as u64).ok(),
+                        Value::U64(v) => Self::try_from(v).ok(),
+                        Value::I8(v) => Self::try_from(v as i64).ok(),
+                        Value::I16(v) => Self::try_from(v as i64).ok(),
+                        Value::I32(v) => Self::try_from(v as i64).ok(),
+                        Value::I64(v) => Self::try_from(v).ok(),
+                        _ => None,
+                    }
+                }
+            }
+        )*
+    };
+}
+
+impl_integer_from_value! { u8 u16 u32 u64 i8 i16 i32 i64 }
+
+impl NumberFromValue for f32 {
+    fn from_value(value: &Value) -> Option<Self> {
+        match *value {
+            Value::F32(v) => Some(v),
+            Value::F64(v) => Some(v as f32),
+            _ => None,
+        }
+    }
+}
+
+impl NumberFromValue for f64 {
+    fn from_value(value: &Value) -> Option<Self> {
+        match *value {
+            Value::F32(v) => Some(v as f64),
+            Value::F64(v) => Some(v),
+            _ => None,
+        }
+    }
+}
+
+// visits numbers with the requested width, so deserialize_u64 on a U8 calls
+// visit_u64, leaving anything that doesn't fit to the visitor's own error
+macro_rules! deserialize_number {
+    ($($func:ident => $target:ident($ty:ty, $visit:ident),)*) => {
+        $(
+            fn $func<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+                let de = match self.newtype_inner() {
+                    Ok(inner) => return inner?.$func(visitor),
+                    Err(de) => de,
+                };
+                if let Value::String(ref v) = *de.value.borrow() {
+                    if de.options.map_key {
+                        return match v.parse::<$ty>() {
+                            Ok(v) => visitor.$visit(v),
+                            Err(..) => Err(de::Error::invalid_value(
+                                de::Unexpected::Str(v),
+                                &concat!("a map key parsable as ", stringify!($ty)),
+                            )),
+                        };
+                    }
+                }
+                let coerced = de.options.coerce_number(de.value.borrow(), NumericTarget::$target);
+                de.options.check_float_narrowing(coerced.as_ref().unwrap_or_else(|| de.value.borrow()), NumericTarget::$target)?;
+                match <$ty as NumberFromValue>::from_value(coerced.as_ref().unwrap_or_else(|| de.value.borrow())) {
+                    Some(v) => visitor.$visit(v),
+                    None => match coerced {
+                        Some(v) => ValueDeserializer::with_options(v, de.options).deserialize_any(visitor),
+                        None => de.deserialize_any(visitor),
+                    },
+                }
+            }
+        )*
+    };
+}
+
+macro_rules! forward_to_value_deserializer {
+    ($de:ident; $($func:ident($($arg:ident: $ty:ty),*))*) => {
+        $(
+            fn $func<V: de::Visitor<'de>>(self, $($arg: $ty,)* visitor: V) -> Result<V::Value, Self::Error> {
+                $de::new(self).$func($($arg,)* visitor)
+            }
+        )*
+    };
+}
+
+/// The numeric type a deserializer was asked for, passed to a
+/// `with_number_coercion` hook.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum NumericTarget {
+    U8,
+    U16,
+    U32,
+    U64,
+    I8,
+    I16,
+    I32,
+    I64,
+    F32,
+    F64,
+}
+
+impl NumericTarget {
+    fn matches(self, value: &Value) -> bool {
+        matches!((self, value),
+            (NumericTarget::U8, Value::U8(..)) |
+            (NumericTarget::U16, Value::U16(..)) |
+            (NumericTarget::U32, Value::U32(..)) |
+            (NumericTarget::U64, Value::U64(..)) |
+            (NumericTarget::I8, Value::I8(..)) |
+            (NumericTarget::I16, Value::I16(..)) |
+            (NumericTarget::I32, Value::I32(..)) |
+            (NumericTarget::I64, Value::I64(..)) |
+            (NumericTarget::F32, Value::F32(..)) |
+            (NumericTarget::F64, Value::F64(..))
+        )
+    }
+}
+
+type NumberCoercion = Arc<dyn Fn(&Value, NumericTarget) -> Option<Value> + Send + Sync>;
+
+#[derive(Clone, Default)]
+struct DeserializerOptions {
+    enum_from_index: bool,
+    max_depth: Option<usize>,
+    max_ops: Option<usize>,
+    // shared by every nested deserializer so the whole tree counts against
+    // max_ops
+    ops: Arc<AtomicUsize>,
+    coerce_bool_from_int: bool,
+    lenient: bool,
+    map_as_pairs: bool,
+    scalar_as_seq: bool,
+    ignore_extra_tuple_elements: bool,
+    strict_float_narrowing: bool,
+    number_as_string: bool,
+    sort_map_entries: bool,
+    case_insensitive_variants: bool,
+    // inverted so that the default options are human-readable
+    compact: bool,
+    parse_map_keys: bool,
+    // set on the options of a map key when parse_map_keys is enabled
+    map_key: bool,
+    number_coercion: Option<NumberCoercion>,
+    // shared by every nested deserializer so errors from inner structs are kept
+    collected: Option<Arc<Mutex<Vec<DeserializerError>>>>,
+    // number of seqs, maps, enums, options and newtypes enclosing the value
+    // being deserialized
+    depth: usize,
+}
+
+// reads 0 and 1 as booleans for `coerce_bool_from_int`, or `None` if
+// `value` is not an integer at all
+fn int_to_bool<E: de::Error>(value: &Value) -> Option<Result<bool, E>> {
+    match *value {
+        Value::U8(0) | Value::U16(0) | Value::U32(0) | Value::U64(0) |
+        Value::I8(0) | Value::I16(0) | Value::I32(0) | Value::I64(0) => Some(Ok(false)),
+        Value::U8(1) | Value::U16(1) | Value::U32(1) | Value::U64(1) |
+        Value::I8(1) | Value::I16(1) | Value::I32(1) | Value::I64(1) => Some(Ok(true)),
+        Value::U8(..) | Value::U16(..) | Value::U32(..) | Value::U64(..) |
+        Value::I8(..) | Value::I16(..) | Value::I32(..) | Value::I64(..) => {
+            Some(Err(de::Error::invalid_value(value.unexpected(), &"0 or 1")))
+        },
+        _ => None,
+    }
+}
+
+// decodes bytes holding exactly one UTF-8 encoded char for `lenient`
+fn bytes_to_char<E: de::Error>(v: &[u8]) -> Result<char, E> {
+    let mut chars = std::str::from_utf8(v).unwrap_or("").chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Ok(c),
+        _ => Err(de::Error::invalid_value(de::Unexpected::Bytes(v), &"a single UTF-8 encoded char")),
+    }
+}
+
+// iterates a map in its own order, or sorted by key for `sort_map_entries`,
+// which is the same thing unless `preserve_order` is enabled
+#[cfg(not(feature = "preserve_order"))]
+fn map_entries(map: ValueMap, _sort: bool) -> map::IntoIter {
+    map.into_iter()
+}
+
+#[cfg(feature = "preserve_order")]
+fn map_entries(mut map: ValueMap, sort: bool) -> map::IntoIter {
+    if sort {
+        map.map.sort_keys();
+    }
+    map.into_iter()
+}
+
+#[cfg(not(feature = "preserve_order"))]
+fn map_entries_ref(map: &ValueMap, _sort: bool) -> map::Iter<'_> {
+    map.iter()
+}
+
+#[cfg(feature = "preserve_order")]
+fn map_entries_ref(map: &ValueMap, sort: bool) -> std::vec::IntoIter<(&Value, &Value)> {
+    let mut entries: Vec<_> = map.iter().collect();
+    if sort {
+        entries.sort_by_key(|&(k, _)| k);
+    }
+    entries.into_iter()
+}
+
+// finds the declared variant named by `variant` for
+// `case_insensitive_variants`, preferring an exact match
+fn match_variant(variant: &Value, variants: &'static [&'static str]) -> Option<&'static str> {
+    match *variant {
+        Value::String(ref v) => variants.iter()
+            .find(|name| **name == v)
+            .or_else(|| variants.iter().find(|name| name.eq_ignore_ascii_case(v)))
+            .cloned(),
+        _ => None,
+    }
+}
+
+// renders numbers, bools and chars for `number_as_string`
+pub(crate) fn scalar_to_string(value: &Value) -> Option<String> {
+    match *value {
+        Value::Bool(v) => Some(v.to_string()),
+        Value::U8(v) => Some(v.to_string()),
+        Value::U16(v) => Some(v.to_string()),
+        Value::U32(v) => Some(v.to_string()),
+        Value::U64(v) => Some(v.to_string()),
+        Value::I8(v) => Some(v.to_string()),
+        Value::I16(v) => Some(v.to_string()),
+        Value::I32(v) => Some(v.to_string()),
+        Value::I64(v) => Some(v.to_string()),
+        Value::F32(v) => Some(v.to_string()),
+        Value::F64(v) => Some(v.to_string()),
+        Value::Char(v) => Some(v.to_string()),
+        _ => None,
+    }
+}
+
+impl DeserializerOptions {
+    fn coerce_number(&self, value: &Value, target: NumericTarget) -> Option<Value> {
+        match self.number_coercion {
+            Some(ref f) if !target.matches(value) => f(value, target),
+            _ => None,
+        }
+    }
+
+    fn check_float_narrowing<E: de::Error>(&self, value: &Value, target: NumericTarget) -> Result<(), E> {
+        match (target, value) {
+            (NumericTarget::F32, &Value::F64(v)) if self.strict_float_narrowing && !v.is_nan() && v as f32 as f64 != v => {
+                Err(de::Error::invalid_value(de::Unexpected::Float(v), &"a float exactly representable as f32"))
+            },
+            _ => Ok(()),
+        }
+    }
+
+    // options for the `len` elements or entries of a seq or map, which are
+    // all counted against max_ops up front
+    fn nested<E: de::Error>(&self, len: usize) -> Result<Self, E> {
+        let ops = self.ops.fetch_add(len, Ordering::Relaxed).saturating_add(len);
+        match (self.max_depth, self.max_ops) {
+            (Some(max_depth), _) if self.depth >= max_depth => {
+                Err(E::custom(format_args!("recursion limit of {} exceeded", max_depth)))
+            },
+            (_, Some(max_ops)) if ops > max_ops => {
+                Err(E::custom(format_args!("operation limit of {} exceeded", max_ops)))
+            },
+            _ => Ok(DeserializerOptions {
+                depth: self.depth + 1,
+                map_key: false,
+                ..self.clone()
+            }),
+        }
+    }
+
+    fn key(&self) -> Self {
+        DeserializerOptions {
+            map_key: self.parse_map_keys,
+            ..self.clone()
+        }
+    }
+}
+
+// the option setters shared by `ValueDeserializer` and
+// `ValueRefDeserializer`, so that both always offer the same options
+macro_rules! deserializer_options {
+    () => {
+        /// Allows enums to be deserialized from an unsigned integer variant index,
+        /// as emitted by some formats for unit variants.
+        pub fn enum_from_index(mut self, enabled: bool) -> Self {
+            self.options.enum_from_index = enabled;
+            self
+        }
+
+        /// Limits how deeply seqs, maps, enums, options and newtypes may be
+        /// nested, returning an error instead of recursing further. Unlimited by
+        /// default.
+        pub fn max_depth(mut self, max_depth: usize) -> Self {
+            self.options.max_depth = Some(max_depth);
+            self
+        }
+
+        /// Limits the total number of seq elements and map entries that may be
+        /// visited across the whole tree, returning an error instead of visiting
+        /// more. Unlike `max_depth` this also bounds wide values. Each seq or map
+        /// is counted in full as soon as it is entered. Unlimited by default.
+        pub fn max_ops(mut self, max_ops: usize) -> Self {
+            self.options.max_ops = Some(max_ops);
+            self
+        }
+
+        /// Accepts values of a related type where a type is requested that the
+        /// value cannot otherwise provide, such as the UTF-8 bytes of a string
+        /// when bytes are requested, or an empty seq or map when unit is
+        /// requested. Strict by default.
+        pub fn lenient(mut self, enabled: bool) -> Self {
+            self.options.lenient = enabled;
+            self
+        }
+
+        /// Allows a seq to be deserialized from a map, whose entries are read
+        /// as `(key, value)` pairs in the map's order, as for `Vec<(K, V)>`.
+        /// Strict by default.
+        pub fn map_as_pairs(mut self, enabled: bool) -> Self {
+            self.options.map_as_pairs = enabled;
+            self
+        }
+
+        /// Allows a seq to be deserialized from any other value, which is
+        /// treated as a seq containing just that value.
+        pub fn scalar_as_seq(mut self, enabled: bool) -> Self {
+            self.options.scalar_as_seq = enabled;
+            self
+        }
+
+        /// Allows a tuple to be deserialized from a seq with more elements than
+        /// the tuple has, ignoring the trailing elements. Strict by default.
+        pub fn ignore_extra_tuple_elements(mut self, enabled: bool) -> Self {
+            self.options.ignore_extra_tuple_elements = enabled;
+            self
+        }
+
+        /// Rejects an `F64` requested as an `f32` unless it converts exactly,
+        /// rather than rounding it to the nearest `f32`. Lossy by default.
+        pub fn strict_float_narrowing(mut self, enabled: bool) -> Self {
+            self.options.strict_float_narrowing = enabled;
+            self
+        }
+
+        /// Allows a number, bool or char to be deserialized as a string, which
+        /// is rendered as by its `Display` implementation. Strict by default.
+        pub fn number_as_string(mut self, enabled: bool) -> Self {
+            self.options.number_as_string = enabled;
+            self
+        }
+
+        /// Visits map entries sorted by key even with the `preserve_order`
+        /// feature, so the result does not depend on the map backend.
+        pub fn sort_map_entries(mut self, enabled: bool) -> Self {
+            self.options.sort_map_entries = enabled;
+            self
+        }
+
+        /// Matches a string enum variant against the variants of the requested
+        /// enum ignoring ASCII case, if no variant matches it exactly.
+        pub fn case_insensitive_variants(mut self, enabled: bool) -> Self {
+            self.options.case_insensitive_variants = enabled;
+            self
+        }
+
+        /// Sets what `is_human_readable` reports to the types being
+        /// deserialized, which is `true` by default. Use `false` for values
+        /// serialized with `HumanReadable::new(.., false)`.
+        ///
+        /// For example, `std::net::IpAddr` is a string like `"127.0.0.1"` when
+        /// human-readable, but otherwise a single-key map from `"V4"` or `"V6"`
+        /// to a seq of the address bytes.
+        pub fn human_readable(mut self, enabled: bool) -> Self {
+            self.options.compact = !enabled;
+            self
+        }
+
+        /// Allows string map keys to be parsed when a number is requested, such
+        /// as the keys of a JSON object deserialized into `HashMap<u32, _>`.
+        pub fn parse_map_keys(mut self, enabled: bool) -> Self {
+            self.options.parse_map_keys = enabled;
+            self
+        }
+
+        /// Allows booleans to be deserialized from the integers `0` and `1`.
+        /// Any other integer is rejected.
+        pub fn coerce_bool_from_int(mut self, enabled: bool) -> Self {
+            self.options.coerce_bool_from_int = enabled;
+            self
+        }
+
+        /// Registers a hook consulted when an integer or float is requested from
+        /// a value of any other variant. Returning `Some` deserializes the
+        /// returned value in its place, while `None` falls back to the default
+        /// strict behaviour.
+        pub fn with_number_coercion<F>(mut self, f: F) -> Self
+            where F: Fn(&Value, NumericTarget) -> Option<Value> + Send + Sync + 'static
+        {
+            self.options.number_coercion = Some(Arc::new(f));
+            self
+        }
+
+        /// Keeps deserializing a struct after an unknown field or a field of the
+        /// wrong type, reporting every problem found in a single error.
+        ///
+        /// Serde cannot resume a struct once one of its fields has failed, so
+        /// only the first type mismatch in each struct is reported. Unknown
+        /// fields are moved after the known ones so that they are all reported
+        /// together, which assumes the struct's field list covers its aliases.
+        /// Once a field fails, the unknown fields that follow it are reported
+        /// too, even if the struct would have ignored them.
+        /// Use `Value::deserialize_into_collecting` to receive the errors as a
+        /// `DeserializerError::Multiple`; other error types get a custom error
+        /// listing every message.
+        pub fn collect_errors(mut self, enabled: bool) -> Self {
+            self.options.collected = if enabled {
+                Some(Default::default())
+            } else {
+                None
+            };
+            self
+        }
+    };
+}
+
+/// Map entries are visited in the iteration order of `ValueMap`: sorted by
+/// key by default, or in insertion order with the `preserve_order` feature
+/// unless `sort_map_entries` is set.
+pub struct ValueDeserializer<E> {
+    value: Value,
+    options: DeserializerOptions,
+    error: PhantomData<fn() -> E>,
+}
+
+impl<E> ValueDeserializer<E> {
+    pub fn new(value: Value) -> Self {
+        Self::with_options(value, Default::default())
+    }
+
+    fn with_options(value: Value, options: DeserializerOptions) -> Self {
+        ValueDeserializer {
+            value,
+            options,
+            error: Default::default(),
+        }
+    }
+
+    deserializer_options!();
+
+    pub fn into_value(self) -> Value {
+        self.value
+    }
+
+    // the wrapped value one level deeper, so nested newtypes count towards
+    // max_depth, or `self` if it is not a newtype
+    #[allow(clippy::result_large_err)]
+    fn newtype_inner(self) -> Result<Result<Self, E>, Self> where E: de::Error {
+        match self.value {
+            Value::Newtype(v) => Ok(self.options.nested(0).map(|options| ValueDeserializer::with_options(*v, options))),
+            _ => Err(self),
+        }
+    }
+}
+
+impl<'de, E> de::Deserializer<'de> for ValueDeserializer<E> where E: de::Error {
+    type Error = E;
+
+    fn is_human_readable(&self) -> bool {
+        !self.options.compact
+    }
+
+    fn deserialize_any<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self.value {
+            Value::Bool(v) => visitor.visit_bool(v),
+            Value::U8(v) => visitor.visit_u8(v),
+            Value::U16(v) => visitor.visit_u16(v),
+            Value::U32(v) => visitor.visit_u32(v),
+            Value::U64(v) => visitor.visit_u64(v),
+            Value::I8(v) => visitor.visit_i8(v),
+            Value::I16(v) => visitor.visit_i16(v),
+            Value::I32(v) => visitor.visit_i32(v),
+            Value::I64(v) => visitor.visit_i64(v),
+            Value::F32(v) => visitor.visit_f32(v),
+            Value::F64(v) => visitor.visit_f64(v),
+            Value::Char(v) => visitor.visit_char(v),
+            Value::String(v) => visitor.visit_string(v),
+            Value::Unit => visitor.visit_unit(),
+            Value::Option(None) => visitor.visit_none(),
+            Value::Option(Some(v)) => visitor.visit_some(ValueDeserializer::with_options(*v, self.options.nested(0)?)),
+            Value::Newtype(v) => visitor.visit_newtype_struct(ValueDeserializer::with_options(*v, self.options.nested(0)?)),
+            Value::Seq(v) => {
+                let options = self.options.nested(v.len())?;
+                let mut seq = de::value::SeqDeserializer::new(v.into_iter().map(|v| {
+                    ValueDeserializer::with_options(v, options.clone())
+                }));
+                let value = visitor.visit_seq(&mut seq)?;
+                seq.end()?;
+                Ok(value)
+            },
+            Value::Map(v) => {
+                let options = self.options.nested(v.len())?;
+                let mut map = de::value::MapDeserializer::new(map_entries(v, self.options.sort_map_entries).map(|(k, v)| (
+                    ValueDeserializer::with_options(k, options.key()),
+                    ValueDeserializer::with_options(v, options.clone()),
+                )));
+                let value = visitor.visit_map(&mut map)?;
+                map.end()?;
+                Ok(value)
+            },
+            Value::Bytes(v) => visitor.visit_byte_buf(v),
+            Value::BigInt(v) => {
+                let options = self.options.nested(1)?;
+                let mut map = de::value::MapDeserializer::new(iter::once((BIGINT_TOKEN, ValueDeserializer::<E>::with_options(Value::Bytes(v), options))));
+                let value = visitor.visit_map(&mut map)?;
+                map.end()?;
+                Ok(value)
+            },
+        }
+    }
+
+    // a newtype is looked through only when it directly wraps an option,
+    // otherwise it is passed to visit_some intact
+    fn deserialize_option<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self.value {
+            Value::Option(..) => self.deserialize_any(visitor),
+            Value::Unit => visitor.visit_unit(),
+            Value::Newtype(v) if matches!(*v, Value::Option(..)) => {
+                ValueDeserializer::with_options(*v, self.options.nested(0)?).deserialize_any(visitor)
+            },
+            _ => visitor.visit_some(self)
+        }
+    }
+
+    fn deserialize_enum<V: de::Visitor<'de>>(self,
+                                             name: &'static str,
+                                             variants: &'static [&'static str],
+                                             visitor: V)
+                                             -> Result<V::Value, Self::Error> {
+        let (variant, value) = match self.value {
+            Value::Newtype(v) => {
+                return ValueDeserializer::with_options(*v, self.options.nested(0)?).deserialize_enum(name, variants, visitor)
+            },
+            Value::Map(value) => {
+                let mut iter = value.into_iter();
+                let (variant, value) = match iter.next() {
+                    Some(v) => v,
+                    None => {
+                        return Err(de::Error::invalid_value(de::Unexpected::Map,
+                                                            &"map with a single key"));
+                    }
+                };
+                // enums are encoded as maps with a single key:value pair
+                if iter.next().is_some() {
+                    return Err(de::Error::invalid_value(de::Unexpected::Map,
+                                                        &"map with a single key"));
+                }
+                (variant, Some(value))
+            }
+            Value::String(variant) => (Value::String(variant), None),
+            Value::U8(..) | Value::U16(..) | Value::U32(..) | Value::U64(..) if self.options.enum_from_index => {
+                (self.value, None)
+            },
+            other => {
+                return Err(de::Error::invalid_type(other.unexpected(), &"string or map"));
+            }
+        };
+
+        let matched = if self.options.case_insensitive_variants {
+            match_variant(&variant, variants)
+        } else {
+            None
+        };
+        let variant = matched.map(|name| Value::String(name.into())).unwrap_or(variant);
+
+        let d = EnumDeserializer {
+            variant,
+            value,
+            options: self.options.nested(0)?,
+            error: Default::default(),
+        };
+        visitor.visit_enum(d)
+    }
+
+    fn deserialize_newtype_struct<V: de::Visitor<'de>>(self,
+                                                       _name: &'static str,
+                                                       visitor: V)
+                                                       -> Result<V::Value, Self::Error> {
+        match self.value {
+            Value::Newtype(v) => visitor.visit_newtype_struct(ValueDeserializer::with_options(*v, self.options.nested(0)?)),
+            _ => visitor.visit_newtype_struct(self),
+        }
+    }
+
+    fn deserialize_bool<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        if self.options.coerce_bool_from_int {
+            if let Some(v) = int_to_bool(&self.value) {
+                return visitor.visit_bool(v?);
+            }
+        }
+        match self.newtype_inner() {
+            Ok(inner) => inner?.deserialize_bool(visitor),
+            Err(de) => de.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_char<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self.value {
+            Value::Bytes(ref v) if self.options.lenient => visitor.visit_char(bytes_to_char(v)?),
+            _ => match self.newtype_inner() {
+                Ok(inner) => inner?.deserialize_char(visitor),
+                Err(de) => de.deserialize_any(visitor),
+            },
+        }
+    }
+
+    fn deserialize_unit<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self.value {
+            Value::Seq(ref v) if self.options.lenient && v.is_empty() => visitor.visit_unit(),
+            Value::Map(ref v) if self.options.lenient && v.is_empty() => visitor.visit_unit(),
+            _ => match self.newtype_inner() {
+                Ok(inner) => inner?.deserialize_unit(visitor),
+                Err(de) => de.deserialize_any(visitor),
+            },
+        }
+    }
+
+    fn deserialize_str<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.deserialize_string(visitor)
+    }
+
+    fn deserialize_string<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        if self.options.number_as_string {
+            if let Some(v) = scalar_to_string(&self.value) {
+                return visitor.visit_string(v);
+            }
+        }
+        match self.newtype_inner() {
+            Ok(inner) => inner?.deserialize_string(visitor),
+            Err(de) => de.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_bytes<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.deserialize_byte_buf(visitor)
+    }
+
+    fn deserialize_byte_buf<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self.value {
+            Value::String(v) if self.options.lenient => visitor.visit_byte_buf(v.into_bytes()),
+            _ => match self.newtype_inner() {
+                Ok(inner) => inner?.deserialize_byte_buf(visitor),
+                Err(de) => de.deserialize_any(visitor),
+            },
+        }
+    }
+
+    fn deserialize_seq<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self.value {
+            Value::Seq(..) => self.deserialize_any(visitor),
+            Value::Newtype(v) => ValueDeserializer::with_options(*v, self.options.nested(0)?).deserialize_seq(visitor),
+            // read maps as seqs of (key, value) pairs such as Vec<(K, V)>
+            Value::Map(v) if self.options.map_as_pairs => {
+                let options = self.options.nested(v.len())?;
+                let mut map = de::value::MapDeserializer::new(map_entries(v, self.options.sort_map_entries).map(|(k, v)| (
+                    ValueDeserializer::with_options(k, options.key()),
+                    ValueDeserializer::with_options(v, options.clone()),
+                )));
+                let value = visitor.visit_seq(&mut map)?;
+                map.end()?;
+                Ok(value)
+            },
+            v if self.options.scalar_as_seq => {
+                let mut seq = de::value::SeqDeserializer::new(iter::once(ValueDeserializer::with_options(v, self.options.nested(1)?)));
+                let value = visitor.visit_seq(&mut seq)?;
+                seq.end()?;
+                Ok(value)
+            },
+            v => ValueDeserializer::with_options(v, self.options).deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_tuple<V: de::Visitor<'de>>(self, len: usize, visitor: V) -> Result<V::Value, Self::Error> {
+        match self.value {
+            // read byte strings as arrays such as [u8; N], counted and
+            // truncated like a seq of U8s
+            Value::Bytes(v) => {
+                let len = if self.options.ignore_extra_tuple_elements { len.min(v.len()) } else { v.len() };
+                let options = self.options.nested(len)?;
+                let mut seq = de::value::SeqDeserializer::new(v.into_iter().take(len).map(|v| {
+                    ValueDeserializer::with_options(Value::U8(v), options.clone())
+                }));
+                let value = visitor.visit_seq(&mut seq)?;
+                seq.end()?;
+                Ok(value)
+            },
+            Value::Seq(v) if self.options.ignore_extra_tuple_elements => {
+                let options = self.options.nested(len.min(v.len()))?;
+                let mut seq = de::value::SeqDeserializer::new(v.into_iter().take(len).map(|v| {
+                    ValueDeserializer::with_options(v, options.clone())
+                }));
+                let value = visitor.visit_seq(&mut seq)?;
+                seq.end()?;
+                Ok(value)
+            },
+            _ => match self.newtype_inner() {
+                Ok(inner) => inner?.deserialize_tuple(len, visitor),
+                Err(de) => de.deserialize_any(visitor),
+            },
+        }
+    }
+
+    fn deserialize_tuple_struct<V: de::Visitor<'de>>(self,
+                                                     name: &'static str,
+                                                     len: usize,
+                                                     visitor: V) -> Result<V::Value, Self::Error> {
+        match self.value {
+            // check the arity up front so that extra elements are reported
+            // against the tuple struct rather than as a generic seq error
+            Value::Seq(ref v) if v.len() < len || (v.len() > len && !self.options.ignore_extra_tuple_elements) => {
+                Err(de::Error::invalid_length(v.len(), &visitor))
+            },
+            Value::Seq(..) => self.deserialize_tuple(len, visitor),
+            _ => match self.newtype_inner() {
+                Ok(inner) => inner?.deserialize_tuple_struct(name, len, visitor),
+                Err(de) => de.deserialize_any(visitor),
+            },
+        }
     }
 
-    fn visit_u32<E>(self, value: u32) -> Result<Value, E> {
-        Ok(Value::U32(value))
+    deserialize_number! {
+        deserialize_u8 => U8(u8, visit_u8),
+        deserialize_u16 => U16(u16, visit_u16),
+        deserialize_u32 => U32(u32, visit_u32),
+        deserialize_u64 => U64(u64, visit_u64),
+        deserialize_i8 => I8(i8, visit_i8),
+        deserialize_i16 => I16(i16, visit_i16),
+        deserialize_i32 => I32(i32, visit_i32),
+        deserialize_i64 => I64(i64, visit_i64),
+        deserialize_f32 => F32(f32, visit_f32),
+        deserialize_f64 => F64(f64, visit_f64),
     }
 
-    fn visit_u64<E>(self, value: u64) -> Result<Value, E> {
-        Ok(Value::U64(value))
+    // struct fields and enum variants are identified by name or by index
+    fn deserialize_identifier<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self.value {
+            Value::String(v) => visitor.visit_string(v),
+            Value::U8(v) => visitor.visit_u64(v.into()),
+            Value::U16(v) => visitor.visit_u64(v.into()),
+            Value::U32(v) => visitor.visit_u64(v.into()),
+            Value::U64(v) => visitor.visit_u64(v),
+            Value::Bytes(v) => visitor.visit_byte_buf(v),
+            _ => match self.newtype_inner() {
+                Ok(inner) => inner?.deserialize_identifier(visitor),
+                Err(de) => de.deserialize_any(visitor),
+            },
+        }
     }
 
-    fn visit_f32<E>(self, value: f32) -> Result<Value, E> {
-        Ok(Value::F32(value))
+    forward_to_deserialize_newtype_inner! {
+        deserialize_map()
+        deserialize_unit_struct(name: &'static str)
     }
 
-    fn visit_f64<E>(self, value: f64) -> Result<Value, E> {
-        Ok(Value::F64(value))
+    fn deserialize_struct<V: de::Visitor<'de>>(self,
+                                               name: &'static str,
+                                               fields: &'static [&'static str],
+                                               visitor: V)
+                                               -> Result<V::Value, Self::Error> {
+        match (self.value, self.options.collected.clone()) {
+            (Value::Newtype(v), _) => {
+                ValueDeserializer::with_options(*v, self.options.nested(0)?).deserialize_struct(name, fields, visitor)
+            },
+            (Value::Map(v), Some(collected)) => {
+                let options = self.options.nested(v.len())?;
+                let entries = map_entries(v, self.options.sort_map_entries).map(|(k, v)| (Cow::Owned(k), Cow::Owned(v)));
+                visitor.visit_map(CollectingMapAccess::new(entries, fields, collected, options))
+            },
+            (value, _) => ValueDeserializer::with_options(value, self.options).deserialize_any(visitor),
+        }
     }
 
-    fn visit_char<E>(self, value: char) -> Result<Value, E> {
-        Ok(Value::Char(value))
+    forward_to_deserialize_any! {
+        ignored_any
     }
+}
 
-    fn visit_str<E>(self, value: &str) -> Result<Value, E> {
-        Ok(Value::String(value.into()))
-    }
+struct CollectingMapAccess<'de, E> {
+    // borrowed when collecting from a `ValueRefDeserializer`
+    entries: std::vec::IntoIter<(Cow<'de, Value>, Cow<'de, Value>)>,
+    value: Option<Cow<'de, Value>>,
+    fields: &'static [&'static str],
+    collected: Arc<Mutex<Vec<DeserializerError>>>,
+    options: DeserializerOptions,
+    error: PhantomData<fn() -> E>,
+}
 
-    fn visit_string<E>(self, value: String) -> Result<Value, E> {
-        Ok(Value::String(value))
+impl<'de, E: de::Error> CollectingMapAccess<'de, E> {
+    fn new<I>(entries: I, fields: &'static [&'static str], collected: Arc<Mutex<Vec<DeserializerError>>>, options: DeserializerOptions) -> Self
+        where I: Iterator<Item = (Cow<'de, Value>, Cow<'de, Value>)>
+    {
+        // known fields first, so that a strict struct only fails once it
+        // reaches the unknown ones
+        let (mut entries, unknown): (Vec<_>, Vec<_>) = entries.partition(|(k, _)| match **k {
+            Value::String(ref k) => fields.contains(&k.as_str()),
+            _ => false,
+        });
+        entries.extend(unknown);
+        CollectingMapAccess {
+            entries: entries.into_iter(),
+            value: None,
+            fields,
+            collected,
+            options,
+            error: PhantomData,
+        }
     }
 
-    fn visit_unit<E>(self) -> Result<Value, E> {
-        Ok(Value::Unit)
+    fn drain_unknown(&mut self, collected: &mut Vec<DeserializerError>) {
+        for (key, _) in self.entries.by_ref() {
+            let key = match *key {
+                Value::String(ref key) if self.fields.contains(&key.as_str()) => continue,
+                Value::String(ref key) => key.clone(),
+                ref key => format!("{:?}", key),
+            };
+            collected.push(DeserializerError::UnknownField(key, self.fields));
+        }
     }
 
-    fn visit_none<E>(self) -> Result<Value, E> {
-        Ok(Value::Option(None))
+    fn fail(&self, errors: &[DeserializerError]) -> E {
+        E::custom(format_args!("{}", errors.iter().map(|e| e.to_string()).collect::<Vec<_>>().join("; ")))
     }
+}
 
-    fn visit_some<D: de::Deserializer<'de>>(self, d: D) -> Result<Value, D::Error> {
-        d.deserialize_any(ValueVisitor).map(|v| Value::Option(Some(Box::new(v))))
+// deserializes a key or value of a collecting struct, borrowing it when the
+// struct was borrowed
+fn deserialize_entry<'de, S: de::DeserializeSeed<'de>>(seed: S, value: Cow<'de, Value>, options: DeserializerOptions) -> Result<S::Value, DeserializerError> {
+    match value {
+        Cow::Owned(value) => seed.deserialize(ValueDeserializer::with_options(value, options)),
+        Cow::Borrowed(value) => seed.deserialize(ValueRefDeserializer::with_options(value, options)),
     }
+}
 
-    fn visit_newtype_struct<D: de::Deserializer<'de>>(self, d: D) -> Result<Value, D::Error> {
-        d.deserialize_any(ValueVisitor).map(|v| Value::Newtype(Box::new(v)))
-    }
+impl<'de, E> de::MapAccess<'de> for CollectingMapAccess<'de, E> where E: de::Error {
+    type Error = E;
 
-    fn visit_seq<V: de::SeqAccess<'de>>(self, mut visitor: V) -> Result<Value, V::Error> {
-        let mut values = Vec::new();
-        while let Some(elem) = visitor.next_element()? {
-            values.push(elem);
+    fn next_key_seed<K: de::DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>, E> {
+        let (key, value) = match self.entries.next() {
+            Some(entry) => entry,
+            None => return Ok(None),
+        };
+        match deserialize_entry(seed, key, self.options.key()) {
+            Ok(key) => {
+                self.value = Some(value);
+                Ok(Some(key))
+            },
+            Err(e) => {
+                // the seed is gone, but everything left over is unknown too
+                let collected = self.collected.clone();
+                let mut collected = collected.lock().unwrap_or_else(|e| e.into_inner());
+                collected.push(e);
+                self.drain_unknown(&mut collected);
+                Err(self.fail(&collected))
+            },
         }
-        Ok(Value::Seq(values))
     }
 
-    fn visit_map<V: de::MapAccess<'de>>(self, mut visitor: V) -> Result<Value, V::Error> {
-        let mut values = BTreeMap::new();
-        while let Some((key, value)) = visitor.next_entry()? {
-            values.insert(key, value);
+    fn next_value_seed<V: de::DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, E> {
+        let value = self.value.take().ok_or_else(|| E::custom("value is missing"))?;
+        let before = self.collected.lock().unwrap_or_else(|e| e.into_inner()).len();
+        match deserialize_entry(seed, value, self.options.clone()) {
+            Ok(value) => Ok(value),
+            Err(e) => {
+                let collected = self.collected.clone();
+                let mut collected = collected.lock().unwrap_or_else(|e| e.into_inner());
+                // a nested struct has already recorded its own errors
+                if collected.len() == before {
+                    collected.push(e);
+                }
+                // the struct stops here, so report the unknown keys it would
+                // have reached
+                self.drain_unknown(&mut collected);
+                Err(self.fail(&collected))
+            },
         }
-        Ok(Value::Map(values))
     }
 
-    fn visit_bytes<E>(self, v: &[u8]) -> Result<Value, E> {
-        Ok(Value::Bytes(v.into()))
-    }
-
-    fn visit_byte_buf<E>(self, v: Vec<u8>) -> Result<Value, E> {
-        Ok(Value::Bytes(v))
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.entries.len())
     }
 }
 
-impl<'de> de::Deserialize<'de> for Value {
-    fn deserialize<D: de::Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
-        d.deserialize_any(ValueVisitor)
+impl<'de, E> de::IntoDeserializer<'de, E> for ValueDeserializer<E> where E: de::Error {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self::Deserializer {
+        self
     }
 }
 
-impl<'de> de::IntoDeserializer<'de, DeserializerError> for Value {
-    type Deserializer = Value;
+impl<'de> de::Deserializer<'de> for Value {
+    type Error = DeserializerError;
 
-    fn into_deserializer(self) -> Value {
-        self
+    forward_to_value_deserializer! {
+        ValueDeserializer;
+        deserialize_any() deserialize_option()
+        deserialize_enum(name: &'static str, variants: &'static [&'static str])
+        deserialize_newtype_struct(name: &'static str)
+        deserialize_bool() deserialize_u8() deserialize_u16() deserialize_u32() deserialize_u64()
+        deserialize_i8() deserialize_i16() deserialize_i32() deserialize_i64()
+        deserialize_f32() deserialize_f64() deserialize_char() deserialize_str() deserialize_string()
+        deserialize_unit() deserialize_seq() deserialize_bytes() deserialize_byte_buf() deserialize_map()
+        deserialize_unit_struct(name: &'static str)
+        deserialize_tuple_struct(name: &'static str, len: usize)
+        deserialize_struct(name: &'static str, fields: &'static [&'static str])
+        deserialize_tuple(len: usize) deserialize_identifier() deserialize_ignored_any()
     }
 }
 
-pub struct ValueDeserializer<E> {
-    value: Value,
+/// Map entries are visited in the iteration order of `ValueMap`: sorted by
+/// key by default, or in insertion order with the `preserve_order` feature
+/// unless `sort_map_entries` is set.
+pub struct ValueRefDeserializer<'a, E> {
+    value: &'a Value,
+    options: DeserializerOptions,
     error: PhantomData<fn() -> E>,
 }
 
-impl<E> ValueDeserializer<E> {
-    pub fn new(value: Value) -> Self {
-        ValueDeserializer {
-            value: value,
+impl<'a, E> ValueRefDeserializer<'a, E> {
+    pub fn new(value: &'a Value) -> Self {
+        Self::with_options(value, Default::default())
+    }
+
+    fn with_options(value: &'a Value, options: DeserializerOptions) -> Self {
+        ValueRefDeserializer {
+            value,
+            options,
             error: Default::default(),
         }
     }
 
-    pub fn into_value(self) -> Value {
+    deserializer_options!();
+
+    pub fn value(&self) -> &'a Value {
         self.value
     }
+
+    fn newtype_inner(self) -> Result<Result<Self, E>, Self> where E: de::Error {
+        match *self.value {
+            Value::Newtype(ref v) => Ok(self.options.nested(0).map(|options| ValueRefDeserializer::with_options(v, options))),
+            _ => Err(self),
+        }
+    }
 }
 
-impl<'de, E> de::Deserializer<'de> for ValueDeserializer<E> where E: de::Error {
+impl<'de, E> de::Deserializer<'de> for ValueRefDeserializer<'de, E> where E: de::Error {
     type Error = E;
 
+    fn is_human_readable(&self) -> bool {
+        !self.options.compact
+    }
+
     fn deserialize_any<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
-        match self.value {
+        match *self.value {
             Value::Bool(v) => visitor.visit_bool(v),
             Value::U8(v) => visitor.visit_u8(v),
             Value::U16(v) => visitor.visit_u16(v),
@@ -340,40 +1591,66 @@ impl<'de, E> de::Deserializer<'de> for ValueDeserializer<E> where E: de::Error {
             Value::F32(v) => visitor.visit_f32(v),
             Value::F64(v) => visitor.visit_f64(v),
             Value::Char(v) => visitor.visit_char(v),
-            Value::String(v) => visitor.visit_string(v),
+            Value::String(ref v) => visitor.visit_borrowed_str(v),
             Value::Unit => visitor.visit_unit(),
             Value::Option(None) => visitor.visit_none(),
-            Value::Option(Some(v)) => visitor.visit_some(ValueDeserializer::new(*v)),
-            Value::Newtype(v) => visitor.visit_newtype_struct(ValueDeserializer::new(*v)),
-            Value::Seq(v) => {
-                visitor.visit_seq(de::value::SeqDeserializer::new(v.into_iter().map(ValueDeserializer::new)))
+            Value::Option(Some(ref v)) => visitor.visit_some(ValueRefDeserializer::with_options(v, self.options.nested(0)?)),
+            Value::Newtype(ref v) => visitor.visit_newtype_struct(ValueRefDeserializer::with_options(v, self.options.nested(0)?)),
+            Value::Seq(ref v) => {
+                let options = self.options.nested(v.len())?;
+                let mut seq = de::value::SeqDeserializer::new(v.iter().map(|v| {
+                    ValueRefDeserializer::with_options(v, options.clone())
+                }));
+                let value = visitor.visit_seq(&mut seq)?;
+                seq.end()?;
+                Ok(value)
             },
-            Value::Map(v) => {
-                visitor.visit_map(de::value::MapDeserializer::new(v.into_iter().map(|(k, v)| (
-                    ValueDeserializer::new(k),
-                    ValueDeserializer::new(v),
-                ))))
+            Value::Map(ref v) => {
+                let options = self.options.nested(v.len())?;
+                let mut map = de::value::MapDeserializer::new(map_entries_ref(v, self.options.sort_map_entries).map(|(k, v)| (
+                    ValueRefDeserializer::with_options(k, options.key()),
+                    ValueRefDeserializer::with_options(v, options.clone()),
+                )));
+                let value = visitor.visit_map(&mut map)?;
+                map.end()?;
+                Ok(value)
+            },
+            Value::Bytes(ref v) => visitor.visit_borrowed_bytes(v),
+            Value::BigInt(ref v) => {
+                // counted like the single-entry map it is visited as
+                self.options.nested::<E>(1)?;
+                let mut map = de::value::MapDeserializer::new(iter::once((BIGINT_TOKEN, de::value::BorrowedBytesDeserializer::<E>::new(v))));
+                let value = visitor.visit_map(&mut map)?;
+                map.end()?;
+                Ok(value)
             },
-            Value::Bytes(v) => visitor.visit_byte_buf(v),
         }
     }
 
+    // a newtype is looked through only when it directly wraps an option,
+    // otherwise it is passed to visit_some intact
     fn deserialize_option<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
-        match self.value {
+        match *self.value {
             Value::Option(..) => self.deserialize_any(visitor),
             Value::Unit => visitor.visit_unit(),
+            Value::Newtype(ref v) if matches!(**v, Value::Option(..)) => {
+                ValueRefDeserializer::with_options(v, self.options.nested(0)?).deserialize_any(visitor)
+            },
             _ => visitor.visit_some(self)
         }
     }
 
     fn deserialize_enum<V: de::Visitor<'de>>(self,
-                                             _name: &'static str,
-                                             _variants: &'static [&'static str],
+                                             name: &'static str,
+                                             variants: &'static [&'static str],
                                              visitor: V)
                                              -> Result<V::Value, Self::Error> {
-        let (variant, value) = match self.value {
-            Value::Map(value) => {
-                let mut iter = value.into_iter();
+        let (variant, value) = match *self.value {
+            Value::Newtype(ref v) => {
+                return ValueRefDeserializer::with_options(v, self.options.nested(0)?).deserialize_enum(name, variants, visitor)
+            },
+            Value::Map(ref value) => {
+                let mut iter = value.iter();
                 let (variant, value) = match iter.next() {
                     Some(v) => v,
                     None => {
@@ -388,15 +1665,26 @@ impl<'de, E> de::Deserializer<'de> for ValueDeserializer<E> where E: de::Error {
                 }
                 (variant, Some(value))
             }
-            Value::String(variant) => (Value::String(variant), None),
-            other => {
+            Value::String(..) => (self.value, None),
+            Value::U8(..) | Value::U16(..) | Value::U32(..) | Value::U64(..) if self.options.enum_from_index => {
+                (self.value, None)
+            },
+            ref other => {
                 return Err(de::Error::invalid_type(other.unexpected(), &"string or map"));
             }
         };
 
-        let d = EnumDeserializer {
-            variant: variant,
-            value: value,
+        let matched = if self.options.case_insensitive_variants {
+            match_variant(variant, variants)
+        } else {
+            None
+        };
+
+        let d = EnumRefDeserializer {
+            variant,
+            matched,
+            value,
+            options: self.options.nested(0)?,
             error: Default::default(),
         };
         visitor.visit_enum(d)
@@ -406,63 +1694,236 @@ impl<'de, E> de::Deserializer<'de> for ValueDeserializer<E> where E: de::Error {
                                                        _name: &'static str,
                                                        visitor: V)
                                                        -> Result<V::Value, Self::Error> {
-        match self.value {
-            Value::Newtype(v) => visitor.visit_newtype_struct(ValueDeserializer::new(*v)),
+        match *self.value {
+            Value::Newtype(ref v) => visitor.visit_newtype_struct(ValueRefDeserializer::with_options(v, self.options.nested(0)?)),
             _ => visitor.visit_newtype_struct(self),
         }
     }
 
-    forward_to_deserialize_any! {
-        bool u8 u16 u32 u64 i8 i16 i32 i64 f32 f64 char str string unit
-        seq bytes byte_buf map unit_struct
-        tuple_struct struct tuple ignored_any identifier
+    fn deserialize_bool<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        if self.options.coerce_bool_from_int {
+            if let Some(v) = int_to_bool(self.value) {
+                return visitor.visit_bool(v?);
+            }
+        }
+        match self.newtype_inner() {
+            Ok(inner) => inner?.deserialize_bool(visitor),
+            Err(de) => de.deserialize_any(visitor),
+        }
     }
-}
 
-impl<'de, E> de::IntoDeserializer<'de, E> for ValueDeserializer<E> where E: de::Error {
-    type Deserializer = Self;
+    fn deserialize_char<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match *self.value {
+            Value::Bytes(ref v) if self.options.lenient => visitor.visit_char(bytes_to_char(v)?),
+            _ => match self.newtype_inner() {
+                Ok(inner) => inner?.deserialize_char(visitor),
+                Err(de) => de.deserialize_any(visitor),
+            },
+        }
+    }
 
-    fn into_deserializer(self) -> Self::Deserializer {
-        self
+    fn deserialize_unit<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match *self.value {
+            Value::Seq(ref v) if self.options.lenient && v.is_empty() => visitor.visit_unit(),
+            Value::Map(ref v) if self.options.lenient && v.is_empty() => visitor.visit_unit(),
+            _ => match self.newtype_inner() {
+                Ok(inner) => inner?.deserialize_unit(visitor),
+                Err(de) => de.deserialize_any(visitor),
+            },
+        }
     }
-}
 
-impl<'de> de::Deserializer<'de> for Value {
-    type Error = DeserializerError;
+    fn deserialize_str<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        if self.options.number_as_string {
+            if let Some(v) = scalar_to_string(self.value) {
+                return visitor.visit_string(v);
+            }
+        }
+        match self.newtype_inner() {
+            Ok(inner) => inner?.deserialize_str(visitor),
+            Err(de) => de.deserialize_any(visitor),
+        }
+    }
 
-    fn deserialize_any<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
-        ValueDeserializer::new(self).deserialize_any(visitor)
+    fn deserialize_string<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.deserialize_str(visitor)
     }
 
-    fn deserialize_option<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
-        ValueDeserializer::new(self).deserialize_option(visitor)
+    fn deserialize_bytes<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match *self.value {
+            Value::String(ref v) if self.options.lenient => visitor.visit_borrowed_bytes(v.as_bytes()),
+            _ => match self.newtype_inner() {
+                Ok(inner) => inner?.deserialize_bytes(visitor),
+                Err(de) => de.deserialize_any(visitor),
+            },
+        }
     }
 
-    fn deserialize_enum<V: de::Visitor<'de>>(self,
-                                             name: &'static str,
-                                             variants: &'static [&'static str],
-                                             visitor: V)
-                                             -> Result<V::Value, Self::Error> {
-        ValueDeserializer::new(self).deserialize_enum(name, variants, visitor)
+    fn deserialize_byte_buf<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.deserialize_bytes(visitor)
     }
 
-    fn deserialize_newtype_struct<V: de::Visitor<'de>>(self,
-                                                       name: &'static str,
-                                                       visitor: V)
-                                                       -> Result<V::Value, Self::Error> {
-        ValueDeserializer::new(self).deserialize_newtype_struct(name, visitor)
+    fn deserialize_seq<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match *self.value {
+            Value::Seq(..) => self.deserialize_any(visitor),
+            Value::Newtype(ref v) => ValueRefDeserializer::with_options(v, self.options.nested(0)?).deserialize_seq(visitor),
+            // read maps as seqs of (key, value) pairs such as Vec<(K, V)>
+            Value::Map(ref v) if self.options.map_as_pairs => {
+                let options = self.options.nested(v.len())?;
+                let mut map = de::value::MapDeserializer::new(map_entries_ref(v, self.options.sort_map_entries).map(|(k, v)| (
+                    ValueRefDeserializer::with_options(k, options.key()),
+                    ValueRefDeserializer::with_options(v, options.clone()),
+                )));
+                let value = visitor.visit_seq(&mut map)?;
+                map.end()?;
+                Ok(value)
+            },
+            ref v if self.options.scalar_as_seq => {
+                let mut seq = de::value::SeqDeserializer::new(iter::once(ValueRefDeserializer::with_options(v, self.options.nested(1)?)));
+                let value = visitor.visit_seq(&mut seq)?;
+                seq.end()?;
+                Ok(value)
+            },
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_tuple<V: de::Visitor<'de>>(self, len: usize, visitor: V) -> Result<V::Value, Self::Error> {
+        match *self.value {
+            // read byte strings as arrays such as [u8; N], counted and
+            // truncated like a seq of U8s
+            Value::Bytes(ref v) => {
+                let len = if self.options.ignore_extra_tuple_elements { len.min(v.len()) } else { v.len() };
+                let options = self.options.nested(len)?;
+                let mut seq = de::value::SeqDeserializer::new(v.iter().take(len).map(|&v| {
+                    ValueDeserializer::with_options(Value::U8(v), options.clone())
+                }));
+                let value = visitor.visit_seq(&mut seq)?;
+                seq.end()?;
+                Ok(value)
+            },
+            Value::Seq(ref v) if self.options.ignore_extra_tuple_elements => {
+                let options = self.options.nested(len.min(v.len()))?;
+                let mut seq = de::value::SeqDeserializer::new(v.iter().take(len).map(|v| {
+                    ValueRefDeserializer::with_options(v, options.clone())
+                }));
+                let value = visitor.visit_seq(&mut seq)?;
+                seq.end()?;
+                Ok(value)
+            },
+            _ => match self.newtype_inner() {
+                Ok(inner) => inner?.deserialize_tuple(len, visitor),
+                Err(de) => de.deserialize_any(visitor),
+            },
+        }
+    }
+
+    fn deserialize_tuple_struct<V: de::Visitor<'de>>(self,
+                                                     name: &'static str,
+                                                     len: usize,
+                                                     visitor: V) -> Result<V::Value, Self::Error> {
+        match *self.value {
+            // check the arity up front so that extra elements are reported
+            // against the tuple struct rather than as a generic seq error
+            Value::Seq(ref v) if v.len() < len || (v.len() > len && !self.options.ignore_extra_tuple_elements) => {
+                Err(de::Error::invalid_length(v.len(), &visitor))
+            },
+            Value::Seq(..) => self.deserialize_tuple(len, visitor),
+            _ => match self.newtype_inner() {
+                Ok(inner) => inner?.deserialize_tuple_struct(name, len, visitor),
+                Err(de) => de.deserialize_any(visitor),
+            },
+        }
+    }
+
+    deserialize_number! {
+        deserialize_u8 => U8(u8, visit_u8),
+        deserialize_u16 => U16(u16, visit_u16),
+        deserialize_u32 => U32(u32, visit_u32),
+        deserialize_u64 => U64(u64, visit_u64),
+        deserialize_i8 => I8(i8, visit_i8),
+        deserialize_i16 => I16(i16, visit_i16),
+        deserialize_i32 => I32(i32, visit_i32),
+        deserialize_i64 => I64(i64, visit_i64),
+        deserialize_f32 => F32(f32, visit_f32),
+        deserialize_f64 => F64(f64, visit_f64),
+    }
+
+    // struct fields and enum variants are identified by name or by index
+    fn deserialize_identifier<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match *self.value {
+            Value::String(ref v) => visitor.visit_borrowed_str(v),
+            Value::U8(v) => visitor.visit_u64(v.into()),
+            Value::U16(v) => visitor.visit_u64(v.into()),
+            Value::U32(v) => visitor.visit_u64(v.into()),
+            Value::U64(v) => visitor.visit_u64(v),
+            Value::Bytes(ref v) => visitor.visit_borrowed_bytes(v),
+            _ => match self.newtype_inner() {
+                Ok(inner) => inner?.deserialize_identifier(visitor),
+                Err(de) => de.deserialize_any(visitor),
+            },
+        }
+    }
+
+    forward_to_deserialize_newtype_inner! {
+        deserialize_map()
+        deserialize_unit_struct(name: &'static str)
+    }
+
+    fn deserialize_struct<V: de::Visitor<'de>>(self,
+                                               name: &'static str,
+                                               fields: &'static [&'static str],
+                                               visitor: V)
+                                               -> Result<V::Value, Self::Error> {
+        match (self.value, self.options.collected.clone()) {
+            (Value::Newtype(v), _) => {
+                ValueRefDeserializer::with_options(v, self.options.nested(0)?).deserialize_struct(name, fields, visitor)
+            },
+            (Value::Map(v), Some(collected)) => {
+                let options = self.options.nested(v.len())?;
+                let entries = map_entries_ref(v, self.options.sort_map_entries).map(|(k, v)| (Cow::Borrowed(k), Cow::Borrowed(v)));
+                visitor.visit_map(CollectingMapAccess::new(entries, fields, collected, options))
+            },
+            (value, _) => ValueRefDeserializer::with_options(value, self.options).deserialize_any(visitor),
+        }
     }
 
     forward_to_deserialize_any! {
-        bool u8 u16 u32 u64 i8 i16 i32 i64 f32 f64 char str string unit
-        seq bytes byte_buf map unit_struct
-        tuple_struct struct tuple ignored_any identifier
+        ignored_any
+    }
+}
+
+impl<'de, E> de::IntoDeserializer<'de, E> for ValueRefDeserializer<'de, E> where E: de::Error {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self::Deserializer {
+        self
+    }
+}
+
+impl<'de> de::Deserializer<'de> for &'de Value {
+    type Error = DeserializerError;
+
+    forward_to_value_deserializer! {
+        ValueRefDeserializer;
+        deserialize_any() deserialize_option()
+        deserialize_enum(name: &'static str, variants: &'static [&'static str])
+        deserialize_newtype_struct(name: &'static str)
+        deserialize_bool() deserialize_u8() deserialize_u16() deserialize_u32() deserialize_u64()
+        deserialize_i8() deserialize_i16() deserialize_i32() deserialize_i64()
+        deserialize_f32() deserialize_f64() deserialize_char() deserialize_str() deserialize_string()
+        deserialize_unit() deserialize_seq() deserialize_bytes() deserialize_byte_buf() deserialize_map()
+        deserialize_unit_struct(name: &'static str)
+        deserialize_tuple_struct(name: &'static str, len: usize)
+        deserialize_struct(name: &'static str, fields: &'static [&'static str])
+        deserialize_tuple(len: usize) deserialize_identifier() deserialize_ignored_any()
     }
 }
 
 struct EnumDeserializer<E> {
     variant: Value,
     value: Option<Value>,
+    options: DeserializerOptions,
     error: PhantomData<fn() -> E>,
 }
 
@@ -475,14 +1936,16 @@ impl<'de, E> de::EnumAccess<'de> for EnumDeserializer<E> where E: de::Error {
     {
         let visitor = VariantDeserializer {
             value: self.value,
+            options: self.options.clone(),
             error: Default::default(),
         };
-        seed.deserialize(ValueDeserializer::new(self.variant)).map(|v| (v, visitor))
+        seed.deserialize(ValueDeserializer::with_options(self.variant, self.options)).map(|v| (v, visitor))
     }
 }
 
 struct VariantDeserializer<E> {
     value: Option<Value>,
+    options: DeserializerOptions,
     error: PhantomData<fn() -> E>,
 }
 
@@ -491,7 +1954,7 @@ impl<'de, E> de::VariantAccess<'de> for VariantDeserializer<E> where E: de::Erro
 
     fn unit_variant(self) -> Result<(), Self::Error> {
         match self.value {
-            Some(value) => de::Deserialize::deserialize(ValueDeserializer::new(value)),
+            Some(value) => de::Deserialize::deserialize(ValueDeserializer::with_options(value, self.options)),
             None => Ok(()),
         }
     }
@@ -500,7 +1963,7 @@ impl<'de, E> de::VariantAccess<'de> for VariantDeserializer<E> where E: de::Erro
         where T: de::DeserializeSeed<'de>
     {
         match self.value {
-            Some(value) => seed.deserialize(ValueDeserializer::new(value)),
+            Some(value) => seed.deserialize(ValueDeserializer::with_options(value, self.options)),
             None => Err(de::Error::invalid_type(de::Unexpected::UnitVariant, &"newtype variant")),
         }
     }
@@ -510,8 +1973,11 @@ impl<'de, E> de::VariantAccess<'de> for VariantDeserializer<E> where E: de::Erro
     {
         match self.value {
             Some(Value::Seq(v)) => {
+                let options = self.options.nested(v.len())?;
                 de::Deserializer::deserialize_any(
-                    de::value::SeqDeserializer::new(v.into_iter().map(ValueDeserializer::new)),
+                    de::value::SeqDeserializer::new(v.into_iter().map(|v| {
+                        ValueDeserializer::with_options(v, options.clone())
+                    })),
                     visitor)
             }
             Some(other) => Err(de::Error::invalid_type(other.unexpected(), &"tuple variant")),
@@ -527,10 +1993,103 @@ impl<'de, E> de::VariantAccess<'de> for VariantDeserializer<E> where E: de::Erro
     {
         match self.value {
             Some(Value::Map(v)) => {
+                let options = self.options.nested(v.len())?;
                 de::Deserializer::deserialize_any(
                     de::value::MapDeserializer::new(v.into_iter().map(|(k, v)| (
-                        ValueDeserializer::new(k),
-                        ValueDeserializer::new(v),
+                        ValueDeserializer::with_options(k, options.key()),
+                        ValueDeserializer::with_options(v, options.clone()),
+                    ))),
+                    visitor)
+            }
+            Some(other) => Err(de::Error::invalid_type(other.unexpected(), &"struct variant")),
+            None => Err(de::Error::invalid_type(de::Unexpected::UnitVariant, &"struct variant")),
+        }
+    }
+}
+
+struct EnumRefDeserializer<'a, E> {
+    variant: &'a Value,
+    // the declared variant name to use instead, for case_insensitive_variants
+    matched: Option<&'static str>,
+    value: Option<&'a Value>,
+    options: DeserializerOptions,
+    error: PhantomData<fn() -> E>,
+}
+
+impl<'de, E> de::EnumAccess<'de> for EnumRefDeserializer<'de, E> where E: de::Error {
+    type Error = E;
+    type Variant = VariantRefDeserializer<'de, Self::Error>;
+
+    fn variant_seed<V>(self, seed: V) -> Result<(V::Value, VariantRefDeserializer<'de, Self::Error>), Self::Error>
+        where V: de::DeserializeSeed<'de>
+    {
+        let visitor = VariantRefDeserializer {
+            value: self.value,
+            options: self.options.clone(),
+            error: Default::default(),
+        };
+        match self.matched {
+            Some(name) => seed.deserialize(de::value::BorrowedStrDeserializer::new(name)),
+            None => seed.deserialize(ValueRefDeserializer::with_options(self.variant, self.options)),
+        }.map(|v| (v, visitor))
+    }
+}
+
+struct VariantRefDeserializer<'a, E> {
+    value: Option<&'a Value>,
+    options: DeserializerOptions,
+    error: PhantomData<fn() -> E>,
+}
+
+impl<'de, E> de::VariantAccess<'de> for VariantRefDeserializer<'de, E> where E: de::Error {
+    type Error = E;
+
+    fn unit_variant(self) -> Result<(), Self::Error> {
+        match self.value {
+            Some(value) => de::Deserialize::deserialize(ValueRefDeserializer::with_options(value, self.options)),
+            None => Ok(()),
+        }
+    }
+
+    fn newtype_variant_seed<T>(self, seed: T) -> Result<T::Value, Self::Error>
+        where T: de::DeserializeSeed<'de>
+    {
+        match self.value {
+            Some(value) => seed.deserialize(ValueRefDeserializer::with_options(value, self.options)),
+            None => Err(de::Error::invalid_type(de::Unexpected::UnitVariant, &"newtype variant")),
+        }
+    }
+
+    fn tuple_variant<V>(self, _len: usize, visitor: V) -> Result<V::Value, Self::Error>
+        where V: de::Visitor<'de>
+    {
+        match self.value {
+            Some(Value::Seq(v)) => {
+                let options = self.options.nested(v.len())?;
+                de::Deserializer::deserialize_any(
+                    de::value::SeqDeserializer::new(v.iter().map(|v| {
+                        ValueRefDeserializer::with_options(v, options.clone())
+                    })),
+                    visitor)
+            }
+            Some(other) => Err(de::Error::invalid_type(other.unexpected(), &"tuple variant")),
+            None => Err(de::Error::invalid_type(de::Unexpected::UnitVariant, &"tuple variant")),
+        }
+    }
+
+    fn struct_variant<V>(self,
+                       _fields: &'static [&'static str],
+                       visitor: V)
+                       -> Result<V::Value, Self::Error>
+        where V: de::Visitor<'de>
+    {
+        match self.value {
+            Some(Value::Map(v)) => {
+                let options = self.options.nested(v.len())?;
+                de::Deserializer::deserialize_any(
+                    de::value::MapDeserializer::new(v.iter().map(|(k, v)| (
+                        ValueRefDeserializer::with_options(k, options.key()),
+                        ValueRefDeserializer::with_options(v, options.clone()),
                     ))),
                     visitor)
             }
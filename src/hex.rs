@@ -0,0 +1,55 @@
+//! Hexadecimal conversions for `Value::Bytes`.
+
+use std::error::Error;
+use std::fmt;
+
+use crate::Value;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum HexError {
+    /// The string, without any `0x` prefix, has this odd number of digits.
+    OddLength(usize),
+    /// The string contains a character that is not a hex digit.
+    InvalidChar(char),
+}
+
+impl fmt::Display for HexError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            HexError::OddLength(len) => write!(f, "Odd number of hex digits: {}", len),
+            HexError::InvalidChar(c) => write!(f, "Invalid hex digit {:?}", c),
+        }
+    }
+}
+
+impl Error for HexError {
+    fn description(&self) -> &str {
+        "Hex decoding error"
+    }
+}
+
+impl Value {
+    /// Encodes `Bytes` as lowercase hex, or returns `None` for any other
+    /// variant.
+    pub fn to_hex(&self) -> Option<String> {
+        match *self {
+            Value::Bytes(ref v) => Some(v.iter().map(|b| format!("{:02x}", b)).collect()),
+            _ => None,
+        }
+    }
+
+    /// Decodes a hex string of either case into `Bytes`, after stripping an
+    /// optional `0x` prefix.
+    pub fn from_hex(s: &str) -> Result<Value, HexError> {
+        let s = s.strip_prefix("0x").unwrap_or(s);
+        if let Some(c) = s.chars().find(|c| !c.is_ascii_hexdigit()) {
+            return Err(HexError::InvalidChar(c))
+        }
+        if s.len() % 2 == 1 {
+            return Err(HexError::OddLength(s.len()))
+        }
+        Ok(Value::Bytes((0..s.len()).step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).expect("validated hex digits"))
+            .collect()))
+    }
+}
@@ -0,0 +1,37 @@
+use std::io;
+
+use crate::Value;
+
+impl Value {
+    /// Parses a JSON document from `reader` into a `Value`.
+    ///
+    /// Integers are stored in the narrowest fitting variant, as with
+    /// `Value::from_deserializer`.
+    pub fn from_json_reader<R: io::Read>(reader: R) -> Result<Value, serde_json::Error> {
+        let mut de = serde_json::Deserializer::from_reader(reader);
+        let value = Value::from_deserializer(&mut de)?;
+        de.end()?;
+        Ok(value)
+    }
+
+    /// Parses a JSON document from a byte slice into a `Value`.
+    ///
+    /// Integers are stored in the narrowest fitting variant, as with
+    /// `Value::from_deserializer`.
+    pub fn from_json_slice(v: &[u8]) -> Result<Value, serde_json::Error> {
+        let mut de = serde_json::Deserializer::from_slice(v);
+        let value = Value::from_deserializer(&mut de)?;
+        de.end()?;
+        Ok(value)
+    }
+
+    /// Serializes `self` as JSON straight into `writer`, without building an
+    /// intermediate string.
+    ///
+    /// Any other format works the same way through `Value`'s `Serialize`
+    /// impl; this is only a shorthand for the common case. Maps with keys
+    /// that JSON cannot represent produce an error.
+    pub fn to_json_writer<W: io::Write>(&self, writer: W) -> Result<(), serde_json::Error> {
+        serde_json::to_writer(writer, self)
+    }
+}
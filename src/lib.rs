@@ -1,17 +1,47 @@
-#![doc(html_root_url="https://docs.rs/serde-value/0.7.0/")]
+#![doc(html_root_url="https://docs.rs/serde-value/0.8.0/")]
 
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashMap};
+use std::borrow::Cow;
 use std::cmp::Ordering;
+use std::fmt;
 use std::hash::{Hash, Hasher};
-use serde::Deserialize;
+use std::mem;
+// only the trait: ciborium turns on serde's derive feature, and the derive
+// macro of the same name would then clash with serde_derive in the tests
+use serde::de::Deserialize;
 use ordered_float::OrderedFloat;
 
 pub use de::*;
 pub use ser::*;
+pub use path::*;
+pub use patch::*;
+pub use builder::*;
+pub use map::ValueMap;
 
 mod de;
 mod ser;
+mod path;
+mod patch;
+mod builder;
+pub mod map;
+mod bytes;
+#[cfg(feature = "toml")]
+mod toml;
+#[cfg(feature = "cbor")]
+mod cbor;
+#[cfg(feature = "json")]
+mod json;
+#[cfg(feature = "query")]
+mod query;
+#[cfg(feature = "hex")]
+mod hex;
+#[cfg(feature = "hex")]
+pub use hex::HexError;
 
+/// A serialized value tree.
+///
+/// Containers own their contents, so `clone()` is always a deep copy of the
+/// whole tree; there is no shallow clone that shares storage.
 #[derive(Clone, Debug)]
 pub enum Value {
     Bool(bool),
@@ -32,12 +62,129 @@ pub enum Value {
     Char(char),
     String(String),
 
+    /// `()` or a unit struct. The name of a unit struct is not kept, so
+    /// deserializing a unit struct accepts a `Unit` of any origin.
     Unit,
     Option(Option<Box<Value>>),
     Newtype(Box<Value>),
     Seq(Vec<Value>),
-    Map(BTreeMap<Value, Value>),
+    Map(ValueMap),
     Bytes(Vec<u8>),
+
+    /// An arbitrary-precision integer as big-endian two's complement bytes,
+    /// where an empty vec is zero. Big integers compare, order and hash by
+    /// the integer they encode, so redundant sign-extension bytes make no
+    /// difference.
+    ///
+    /// Serializing standard integer types never produces this variant. It
+    /// only comes from formats with big integers, such as CBOR bignums, and
+    /// passes through serde as a single-field struct with a private name so
+    /// that it survives a round trip through `to_value` or `Value::deserialize`.
+    /// That struct is only understood by serde-value itself: other formats
+    /// such as JSON write it out as a map with that private key, which does
+    /// not read back as a number.
+    BigInt(Vec<u8>),
+}
+
+// the name of the struct, and of its only field, that `Value::BigInt` is
+// serialized as
+pub(crate) const BIGINT_TOKEN: &str = "$serde_value::private::BigInt";
+
+// turns the map that a `Value::BigInt` was serialized as back into one
+pub(crate) fn map_value(map: ValueMap) -> Value {
+    match map.iter().next() {
+        Some((Value::String(k), Value::Bytes(..))) if map.len() == 1 && k == BIGINT_TOKEN => match map.into_iter().next() {
+            Some((_, Value::Bytes(v))) => Value::BigInt(v),
+            _ => unreachable!(),
+        },
+        _ => Value::Map(map),
+    }
+}
+
+// strips the redundant sign-extension bytes from a `Value::BigInt`, so that
+// every integer has a single encoding and zero is empty
+fn bigint_minimal(mut v: &[u8]) -> &[u8] {
+    while let [b0, b1, ..] = *v {
+        if (b0 == 0x00 && b1 & 0x80 == 0) || (b0 == 0xff && b1 & 0x80 != 0) {
+            v = &v[1..];
+        } else {
+            break
+        }
+    }
+    if v == [0x00] { &[] } else { v }
+}
+
+// orders two `Value::BigInt`s by the integers they encode: sign first, then
+// the length of the minimal encoding, then its bytes
+fn bigint_cmp(v0: &[u8], v1: &[u8]) -> Ordering {
+    let (v0, v1) = (bigint_minimal(v0), bigint_minimal(v1));
+    let negative = |v: &[u8]| v.first().is_some_and(|b| b & 0x80 != 0);
+    match (negative(v0), negative(v1)) {
+        (true, false) => Ordering::Less,
+        (false, true) => Ordering::Greater,
+        (true, true) => v1.len().cmp(&v0.len()).then_with(|| v0.cmp(v1)),
+        (false, false) => v0.len().cmp(&v1.len()).then_with(|| v0.cmp(v1)),
+    }
+}
+
+/// The broad category of a `Value`, with every integer and float variant
+/// grouped under `Number`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ValueKind {
+    Bool,
+    Number,
+    Char,
+    String,
+    Unit,
+    Option,
+    Newtype,
+    Seq,
+    Map,
+    Bytes,
+}
+
+impl fmt::Display for ValueKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            ValueKind::Bool => "a boolean",
+            ValueKind::Number => "a number",
+            ValueKind::Char => "a char",
+            ValueKind::String => "a string",
+            ValueKind::Unit => "unit",
+            ValueKind::Option => "an option",
+            ValueKind::Newtype => "a newtype struct",
+            ValueKind::Seq => "a sequence",
+            ValueKind::Map => "a map",
+            ValueKind::Bytes => "a byte array",
+        })
+    }
+}
+
+/// The kinds of the elements of a seq, as returned by
+/// `Value::seq_element_kind`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SeqKind {
+    Empty,
+    Homogeneous(ValueKind),
+    /// Every kind present, in order of first appearance.
+    Mixed(Vec<ValueKind>),
+}
+
+/// A key that appeared more than once, as returned by
+/// `Value::from_pairs_checked`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DuplicateKeyError(pub Value);
+
+impl fmt::Display for DuplicateKeyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Duplicate map key {:?}", self.0)
+    }
+}
+
+impl std::error::Error for DuplicateKeyError {
+    fn description(&self) -> &str {
+        "Duplicate map key"
+    }
 }
 
 impl Hash for Value {
@@ -60,12 +207,13 @@ impl Hash for Value {
             Value::F64(v) => OrderedFloat(v).hash(hasher),
             Value::Char(v) => v.hash(hasher),
             Value::String(ref v) => v.hash(hasher),
-            Value::Unit => ().hash(hasher),
+            Value::Unit => (),
             Value::Option(ref v) => v.hash(hasher),
             Value::Newtype(ref v) => v.hash(hasher),
             Value::Seq(ref v) => v.hash(hasher),
             Value::Map(ref v) => v.hash(hasher),
             Value::Bytes(ref v) => v.hash(hasher),
+            Value::BigInt(ref v) => bigint_minimal(v).hash(hasher),
         }
     }
 }
@@ -73,25 +221,26 @@ impl Hash for Value {
 impl PartialEq for Value {
     fn eq(&self, rhs: &Self) -> bool {
         match (self, rhs) {
-            (&Value::Bool(v0), &Value::Bool(v1)) if v0 == v1 => true,
-            (&Value::U8(v0), &Value::U8(v1)) if v0 == v1 => true,
-            (&Value::U16(v0), &Value::U16(v1)) if v0 == v1 => true,
-            (&Value::U32(v0), &Value::U32(v1)) if v0 == v1 => true,
-            (&Value::U64(v0), &Value::U64(v1)) if v0 == v1 => true,
-            (&Value::I8(v0), &Value::I8(v1)) if v0 == v1 => true,
-            (&Value::I16(v0), &Value::I16(v1)) if v0 == v1 => true,
-            (&Value::I32(v0), &Value::I32(v1)) if v0 == v1 => true,
-            (&Value::I64(v0), &Value::I64(v1)) if v0 == v1 => true,
-            (&Value::F32(v0), &Value::F32(v1)) if OrderedFloat(v0) == OrderedFloat(v1) => true,
-            (&Value::F64(v0), &Value::F64(v1)) if OrderedFloat(v0) == OrderedFloat(v1) => true,
-            (&Value::Char(v0), &Value::Char(v1)) if v0 == v1 => true,
-            (&Value::String(ref v0), &Value::String(ref v1)) if v0 == v1 => true,
-            (&Value::Unit, &Value::Unit) => true,
-            (&Value::Option(ref v0), &Value::Option(ref v1)) if v0 == v1 => true,
-            (&Value::Newtype(ref v0), &Value::Newtype(ref v1)) if v0 == v1 => true,
-            (&Value::Seq(ref v0), &Value::Seq(ref v1)) if v0 == v1 => true,
-            (&Value::Map(ref v0), &Value::Map(ref v1)) if v0 == v1 => true,
-            (&Value::Bytes(ref v0), &Value::Bytes(ref v1)) if v0 == v1 => true,
+            (Value::Bool(v0), Value::Bool(v1)) if v0 == v1 => true,
+            (Value::U8(v0), Value::U8(v1)) if v0 == v1 => true,
+            (Value::U16(v0), Value::U16(v1)) if v0 == v1 => true,
+            (Value::U32(v0), Value::U32(v1)) if v0 == v1 => true,
+            (Value::U64(v0), Value::U64(v1)) if v0 == v1 => true,
+            (Value::I8(v0), Value::I8(v1)) if v0 == v1 => true,
+            (Value::I16(v0), Value::I16(v1)) if v0 == v1 => true,
+            (Value::I32(v0), Value::I32(v1)) if v0 == v1 => true,
+            (Value::I64(v0), Value::I64(v1)) if v0 == v1 => true,
+            (Value::F32(v0), Value::F32(v1)) if OrderedFloat(*v0) == OrderedFloat(*v1) => true,
+            (Value::F64(v0), Value::F64(v1)) if OrderedFloat(*v0) == OrderedFloat(*v1) => true,
+            (Value::Char(v0), Value::Char(v1)) if v0 == v1 => true,
+            (Value::String(v0), Value::String(v1)) if v0 == v1 => true,
+            (Value::Unit, Value::Unit) => true,
+            (Value::Option(v0), Value::Option(v1)) if v0 == v1 => true,
+            (Value::Newtype(v0), Value::Newtype(v1)) if v0 == v1 => true,
+            (Value::Seq(v0), Value::Seq(v1)) if v0 == v1 => true,
+            (Value::Map(v0), Value::Map(v1)) if v0.sorted_entries().eq(v1.sorted_entries()) => true,
+            (Value::Bytes(v0), Value::Bytes(v1)) if v0 == v1 => true,
+            (Value::BigInt(v0), Value::BigInt(v1)) if bigint_minimal(v0) == bigint_minimal(v1) => true,
             _ => false,
         }
     }
@@ -100,26 +249,27 @@ impl PartialEq for Value {
 impl Ord for Value {
     fn cmp(&self, rhs: &Self) -> Ordering {
         match (self, rhs) {
-            (&Value::Bool(v0), &Value::Bool(ref v1)) => v0.cmp(v1),
-            (&Value::U8(v0), &Value::U8(ref v1)) => v0.cmp(v1),
-            (&Value::U16(v0), &Value::U16(ref v1)) => v0.cmp(v1),
-            (&Value::U32(v0), &Value::U32(ref v1)) => v0.cmp(v1),
-            (&Value::U64(v0), &Value::U64(ref v1)) => v0.cmp(v1),
-            (&Value::I8(v0), &Value::I8(ref v1)) => v0.cmp(v1),
-            (&Value::I16(v0), &Value::I16(ref v1)) => v0.cmp(v1),
-            (&Value::I32(v0), &Value::I32(ref v1)) => v0.cmp(v1),
-            (&Value::I64(v0), &Value::I64(ref v1)) => v0.cmp(v1),
-            (&Value::F32(v0), &Value::F32(v1)) => OrderedFloat(v0).cmp(&OrderedFloat(v1)),
-            (&Value::F64(v0), &Value::F64(v1)) => OrderedFloat(v0).cmp(&OrderedFloat(v1)),
-            (&Value::Char(v0), &Value::Char(ref v1)) => v0.cmp(v1),
-            (&Value::String(ref v0), &Value::String(ref v1)) => v0.cmp(v1),
-            (&Value::Unit, &Value::Unit) => Ordering::Equal,
-            (&Value::Option(ref v0), &Value::Option(ref v1)) => v0.cmp(v1),
-            (&Value::Newtype(ref v0), &Value::Newtype(ref v1)) => v0.cmp(v1),
-            (&Value::Seq(ref v0), &Value::Seq(ref v1)) => v0.cmp(v1),
-            (&Value::Map(ref v0), &Value::Map(ref v1)) => v0.cmp(v1),
-            (&Value::Bytes(ref v0), &Value::Bytes(ref v1)) => v0.cmp(v1),
-            (ref v0, ref v1) => v0.discriminant().cmp(&v1.discriminant()),
+            (Value::Bool(v0), Value::Bool(v1)) => v0.cmp(v1),
+            (Value::U8(v0), Value::U8(v1)) => v0.cmp(v1),
+            (Value::U16(v0), Value::U16(v1)) => v0.cmp(v1),
+            (Value::U32(v0), Value::U32(v1)) => v0.cmp(v1),
+            (Value::U64(v0), Value::U64(v1)) => v0.cmp(v1),
+            (Value::I8(v0), Value::I8(v1)) => v0.cmp(v1),
+            (Value::I16(v0), Value::I16(v1)) => v0.cmp(v1),
+            (Value::I32(v0), Value::I32(v1)) => v0.cmp(v1),
+            (Value::I64(v0), Value::I64(v1)) => v0.cmp(v1),
+            (Value::F32(v0), Value::F32(v1)) => OrderedFloat(*v0).cmp(&OrderedFloat(*v1)),
+            (Value::F64(v0), Value::F64(v1)) => OrderedFloat(*v0).cmp(&OrderedFloat(*v1)),
+            (Value::Char(v0), Value::Char(v1)) => v0.cmp(v1),
+            (Value::String(v0), Value::String(v1)) => v0.cmp(v1),
+            (Value::Unit, Value::Unit) => Ordering::Equal,
+            (Value::Option(v0), Value::Option(v1)) => v0.cmp(v1),
+            (Value::Newtype(v0), Value::Newtype(v1)) => v0.cmp(v1),
+            (Value::Seq(v0), Value::Seq(v1)) => v0.cmp(v1),
+            (Value::Map(v0), Value::Map(v1)) => v0.sorted_entries().cmp(v1.sorted_entries()),
+            (Value::Bytes(v0), Value::Bytes(v1)) => v0.cmp(v1),
+            (Value::BigInt(v0), Value::BigInt(v1)) => bigint_cmp(v0, v1),
+            (v0, v1) => v0.discriminant().cmp(&v1.discriminant()),
         }
     }
 }
@@ -146,10 +296,11 @@ impl Value {
             Value::Seq(..) => 16,
             Value::Map(..) => 17,
             Value::Bytes(..) => 18,
+            Value::BigInt(..) => 19,
         }
     }
 
-    fn unexpected(&self) -> serde::de::Unexpected {
+    fn unexpected(&self) -> serde::de::Unexpected<'_> {
         match *self {
             Value::Bool(b) => serde::de::Unexpected::Bool(b),
             Value::U8(n) => serde::de::Unexpected::Unsigned(n as u64),
@@ -170,12 +321,704 @@ impl Value {
             Value::Seq(_) => serde::de::Unexpected::Seq,
             Value::Map(_) => serde::de::Unexpected::Map,
             Value::Bytes(ref b) => serde::de::Unexpected::Bytes(b),
+            Value::BigInt(..) => serde::de::Unexpected::Other("big integer"),
+        }
+    }
+
+    pub fn kind(&self) -> ValueKind {
+        match *self {
+            Value::Bool(..) => ValueKind::Bool,
+            Value::U8(..) | Value::U16(..) | Value::U32(..) | Value::U64(..) |
+            Value::I8(..) | Value::I16(..) | Value::I32(..) | Value::I64(..) |
+            Value::F32(..) | Value::F64(..) | Value::BigInt(..) => ValueKind::Number,
+            Value::Char(..) => ValueKind::Char,
+            Value::String(..) => ValueKind::String,
+            Value::Unit => ValueKind::Unit,
+            Value::Option(..) => ValueKind::Option,
+            Value::Newtype(..) => ValueKind::Newtype,
+            Value::Seq(..) => ValueKind::Seq,
+            Value::Map(..) => ValueKind::Map,
+            Value::Bytes(..) => ValueKind::Bytes,
+        }
+    }
+
+    pub fn is_number(&self) -> bool {
+        self.kind() == ValueKind::Number
+    }
+
+    /// Describes the kinds of the elements of a seq, or returns `None` if
+    /// `self` is not a seq.
+    pub fn seq_element_kind(&self) -> Option<SeqKind> {
+        let seq = match *self {
+            Value::Seq(ref v) => v,
+            _ => return None,
+        };
+        let mut kinds = Vec::new();
+        for kind in seq.iter().map(Value::kind) {
+            if !kinds.contains(&kind) {
+                kinds.push(kind);
+            }
+        }
+        Some(match kinds.len() {
+            0 => SeqKind::Empty,
+            1 => SeqKind::Homogeneous(kinds[0]),
+            _ => SeqKind::Mixed(kinds),
+        })
+    }
+
+    /// Returns `true` for a non-empty seq whose elements are all of the same
+    /// kind.
+    pub fn is_homogeneous_seq(&self) -> bool {
+        matches!(self.seq_element_kind(), Some(SeqKind::Homogeneous(..)))
+    }
+
+    /// Returns `self` if it is of the given kind, or an `InvalidType` error
+    /// describing the mismatch otherwise.
+    pub fn expect_kind(&self, kind: ValueKind) -> Result<&Value, DeserializerError> {
+        if self.kind() == kind {
+            Ok(self)
+        } else {
+            Err(serde::de::Error::invalid_type(self.unexpected(), &kind.to_string().as_str()))
         }
     }
 
+    /// Describes this value for error messages, like serde's `Unexpected`
+    /// but owning its contents so that it can outlive `self`.
+    pub fn to_unexpected(&self) -> Unexpected {
+        self.unexpected().into()
+    }
+
+    /// Deserializes `self` into any `Deserialize` type.
+    ///
+    /// Serde offers no way to detect that `T` is `Value` itself, so that case
+    /// still walks and rebuilds the whole tree. Code that only passes a
+    /// `Value` through should move or clone it instead.
     pub fn deserialize_into<'de, T: Deserialize<'de>>(self) -> Result<T, DeserializerError> {
         T::deserialize(self)
     }
+
+    /// Runs `validate` on `self` before deserializing it, turning a
+    /// validation failure into `DeserializerError::Custom`.
+    pub fn deserialize_into_validated<'de, T, F>(self, validate: F) -> Result<T, DeserializerError>
+        where T: Deserialize<'de>, F: FnOnce(&Value) -> Result<(), String>
+    {
+        validate(&self).map_err(DeserializerError::Custom)?;
+        T::deserialize(self)
+    }
+
+    /// Structurally compares two values, allowing `F32` and `F64` leaves to
+    /// differ by up to `epsilon`. All other leaves must match exactly.
+    pub fn approx_eq(&self, other: &Value, epsilon: f64) -> bool {
+        self.approx_eq_with(other, epsilon, false)
+    }
+
+    /// Like `approx_eq`, but any two numeric leaves are compared as `f64`,
+    /// so integers may also match floats.
+    pub fn approx_eq_numeric(&self, other: &Value, epsilon: f64) -> bool {
+        self.approx_eq_with(other, epsilon, true)
+    }
+
+    /// Compares two values as data that may have passed through different
+    /// formats: integers of any width are equal if they hold the same
+    /// number, other numbers are compared as by `approx_eq_numeric` with no
+    /// tolerance, and `Bytes` equals a seq of integers with the same byte
+    /// values, as produced by formats without a byte string type.
+    pub fn data_eq(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::Bytes(b), Value::Seq(s)) | (Value::Seq(s), Value::Bytes(b)) => {
+                b.len() == s.len() && b.iter().zip(s).all(|(&b, v)| v.as_i128() == Some(b.into()))
+            },
+            (Value::Option(Some(v0)), Value::Option(Some(v1))) => v0.data_eq(v1),
+            (Value::Newtype(v0), Value::Newtype(v1)) => v0.data_eq(v1),
+            (Value::Seq(v0), Value::Seq(v1)) => {
+                v0.len() == v1.len() && v0.iter().zip(v1).all(|(v0, v1)| v0.data_eq(v1))
+            },
+            (Value::Map(v0), Value::Map(v1)) => {
+                v0.len() == v1.len() &&
+                    v0.sorted_entries().zip(v1.sorted_entries()).all(|((k0, v0), (k1, v1))| k0 == k1 && v0.data_eq(v1))
+            },
+            (v0, v1) => match (v0.as_i128(), v1.as_i128()) {
+                (Some(i0), Some(i1)) => i0 == i1,
+                _ => v0.approx_eq_numeric(v1, 0.0),
+            },
+        }
+    }
+
+    /// Compares two values like `==`, but skips map entries whose key is a
+    /// `String` listed in `ignore`, at any depth, such as volatile timestamp
+    /// fields in test assertions.
+    pub fn eq_ignoring_keys(&self, other: &Value, ignore: &[&str]) -> bool {
+        match (self, other) {
+            (Value::Option(Some(v0)), Value::Option(Some(v1))) => v0.eq_ignoring_keys(v1, ignore),
+            (Value::Newtype(v0), Value::Newtype(v1)) => v0.eq_ignoring_keys(v1, ignore),
+            (Value::Seq(v0), Value::Seq(v1)) => {
+                v0.len() == v1.len() && v0.iter().zip(v1).all(|(v0, v1)| v0.eq_ignoring_keys(v1, ignore))
+            },
+            (Value::Map(v0), Value::Map(v1)) => {
+                let kept = |&(k, _): &(&Value, &Value)| match *k {
+                    Value::String(ref k) => !ignore.contains(&&k[..]),
+                    _ => true,
+                };
+                let mut e0 = v0.sorted_entries().filter(kept);
+                let mut e1 = v1.sorted_entries().filter(kept);
+                loop {
+                    match (e0.next(), e1.next()) {
+                        (None, None) => return true,
+                        (Some((k0, v0)), Some((k1, v1))) if k0 == k1 && v0.eq_ignoring_keys(v1, ignore) => (),
+                        _ => return false,
+                    }
+                }
+            },
+            (v0, v1) => v0 == v1,
+        }
+    }
+
+    /// Returns whether `other` contains `self`: every entry of a map in
+    /// `self` must have an entry with an equal key in the corresponding map of
+    /// `other`, whose value contains the value from `self` in turn. Seqs and
+    /// all other values must be equal, as with `==`.
+    pub fn is_subset_of(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::Option(Some(v0)), Value::Option(Some(v1))) => v0.is_subset_of(v1),
+            (Value::Newtype(v0), Value::Newtype(v1)) => v0.is_subset_of(v1),
+            (Value::Map(v0), Value::Map(v1)) => {
+                v0.iter().all(|(k, v0)| v1.get(k).is_some_and(|v1| v0.is_subset_of(v1)))
+            },
+            (v0, v1) => v0 == v1,
+        }
+    }
+
+    fn approx_eq_with(&self, other: &Value, epsilon: f64, numeric: bool) -> bool {
+        match (self, other) {
+            (Value::Option(Some(v0)), Value::Option(Some(v1))) => v0.approx_eq_with(v1, epsilon, numeric),
+            (Value::Newtype(v0), Value::Newtype(v1)) => v0.approx_eq_with(v1, epsilon, numeric),
+            (Value::Seq(v0), Value::Seq(v1)) => {
+                v0.len() == v1.len() &&
+                    v0.iter().zip(v1).all(|(v0, v1)| v0.approx_eq_with(v1, epsilon, numeric))
+            },
+            (Value::Map(v0), Value::Map(v1)) => {
+                v0.len() == v1.len() &&
+                    v0.sorted_entries().zip(v1.sorted_entries()).all(|((k0, v0), (k1, v1))| k0 == k1 && v0.approx_eq_with(v1, epsilon, numeric))
+            },
+            (v0, v1) => {
+                let (f0, f1) = if numeric {
+                    (v0.as_f64_lossy(), v1.as_f64_lossy())
+                } else {
+                    (v0.as_float(), v1.as_float())
+                };
+                match (f0, f1) {
+                    (Some(f0), Some(f1)) => OrderedFloat(f0) == OrderedFloat(f1) || (f0 - f1).abs() <= epsilon,
+                    _ => v0 == v1,
+                }
+            },
+        }
+    }
+
+    /// Returns the total number of nodes in the tree, including `self` and
+    /// both the keys and values of every map.
+    pub fn node_count(&self) -> usize {
+        1 + match *self {
+            Value::Option(Some(ref v)) | Value::Newtype(ref v) => v.node_count(),
+            Value::Seq(ref v) => v.iter().map(Value::node_count).sum(),
+            Value::Map(ref v) => v.iter().map(|(k, v)| k.node_count() + v.node_count()).sum(),
+            _ => 0,
+        }
+    }
+
+    /// Returns the maximum nesting depth of the tree, where a value with no
+    /// children has a depth of 1. Map keys count towards the depth.
+    pub fn depth(&self) -> usize {
+        1 + match *self {
+            Value::Option(Some(ref v)) | Value::Newtype(ref v) => v.depth(),
+            Value::Seq(ref v) => v.iter().map(Value::depth).max().unwrap_or(0),
+            Value::Map(ref v) => v.iter().map(|(k, v)| k.depth().max(v.depth())).max().unwrap_or(0),
+            _ => 0,
+        }
+    }
+
+    /// Returns a mutable reference to the value for `key`, inserting `f()`
+    /// first if it is absent. Returns `None` if `self` is not a map.
+    pub fn get_or_insert_with<K: Into<Value>, F: FnOnce() -> Value>(&mut self, key: K, f: F) -> Option<&mut Value> {
+        match *self {
+            Value::Map(ref mut map) => Some(map.entry(key.into()).or_insert_with(f)),
+            _ => None,
+        }
+    }
+
+    /// Returns the first of `values` that is neither `Unit` nor
+    /// `Option(None)`, or `Unit` if there is none, like SQL's `COALESCE`.
+    pub fn coalesce<I: IntoIterator<Item = Value>>(values: I) -> Value {
+        values.into_iter()
+            .find(|v| !v.is_null())
+            .unwrap_or(Value::Unit)
+    }
+
+    /// Merges a seq of maps into a single map, such as the lists of
+    /// single-entry objects emitted by some formats. When a key appears in
+    /// more than one map, the value from the later map wins.
+    ///
+    /// Returns `None` if `self` is not a seq or any element is not a map.
+    pub fn seq_to_map(&self) -> Option<Value> {
+        let mut merged = ValueMap::new();
+        match *self {
+            Value::Seq(ref seq) => for v in seq {
+                match *v {
+                    Value::Map(ref map) => merged.extend(map.iter().map(|(k, v)| (k.clone(), v.clone()))),
+                    _ => return None,
+                }
+            },
+            _ => return None,
+        }
+        Some(Value::Map(merged))
+    }
+
+    /// Returns the entries of a map sorted by key, whatever order the map
+    /// itself keeps them in, or `None` if `self` is not a map.
+    pub fn ordered_pairs(&self) -> Option<Vec<(&Value, &Value)>> {
+        match *self {
+            Value::Map(ref map) => Some(map.sorted_entries().collect()),
+            _ => None,
+        }
+    }
+
+    /// Returns the contents of a map, or `self` unchanged if it is not one.
+    pub fn into_map(self) -> Result<ValueMap, Value> {
+        match self {
+            Value::Map(v) => Ok(v),
+            v => Err(v),
+        }
+    }
+
+    /// Returns the contents of a seq, or `self` unchanged if it is not one.
+    ///
+    /// Unlike `deserialize_into::<Vec<Value>>()`, which rebuilds every
+    /// element, this moves the existing vec out.
+    pub fn into_seq(self) -> Result<Vec<Value>, Value> {
+        match self {
+            Value::Seq(v) => Ok(v),
+            v => Err(v),
+        }
+    }
+
+    /// Builds a nested map from flat keys such as environment variables,
+    /// splitting each key on `separator`: `DB__PORT=5432` with a separator
+    /// of `__` becomes `{"DB": {"PORT": "5432"}}`. Values are left as
+    /// strings.
+    ///
+    /// Pairs are applied in order and the last one wins when a key is used
+    /// both as a scalar and as a nested map: `A=1` followed by `A__B=2`
+    /// replaces the string with a map, and the reverse replaces the map.
+    pub fn from_flat<I: IntoIterator<Item = (String, String)>>(pairs: I, separator: &str) -> Value {
+        let mut root = ValueMap::new();
+        for (key, value) in pairs {
+            insert_nested(&mut root, key.split(separator), Value::String(value));
+        }
+        Value::Map(root)
+    }
+
+    /// Builds a map from `pairs`, keeping the last value for a repeated key.
+    pub fn from_pairs_last_wins(pairs: Vec<(Value, Value)>) -> Value {
+        let mut map = ValueMap::new();
+        for (k, v) in pairs {
+            map.insert(k, v);
+        }
+        Value::Map(map)
+    }
+
+    /// Builds a map from `pairs`, failing on the first key that repeats an
+    /// earlier one.
+    pub fn from_pairs_checked(pairs: Vec<(Value, Value)>) -> Result<Value, DuplicateKeyError> {
+        let mut map = ValueMap::new();
+        for (k, v) in pairs {
+            if map.contains_key(&k) {
+                return Err(DuplicateKeyError(k))
+            }
+            map.insert(k, v);
+        }
+        Ok(Value::Map(map))
+    }
+
+    /// Builds a map from `pairs`, keeping the first value for a repeated key.
+    pub fn from_pairs_first_wins(pairs: Vec<(Value, Value)>) -> Value {
+        let mut map = ValueMap::new();
+        for (k, v) in pairs {
+            map.entry(k).or_insert(v);
+        }
+        Value::Map(map)
+    }
+
+    /// Splits a map into the entries for which `f` returns `true` and those
+    /// for which it returns `false`. If `self` is not a map it is returned
+    /// unchanged alongside an empty map.
+    pub fn partition_map<F: FnMut(&Value, &Value) -> bool>(self, mut f: F) -> (Value, Value) {
+        match self {
+            Value::Map(map) => {
+                let (matching, rest) = map.into_iter().partition(|(k, v)| f(k, v));
+                (Value::Map(matching), Value::Map(rest))
+            },
+            v => (v, Value::Map(ValueMap::new())),
+        }
+    }
+
+    /// Converts a map whose keys are all strings into a `HashMap`, returning
+    /// `self` unchanged if it is not such a map.
+    pub fn into_string_map(self) -> Result<HashMap<String, Value>, Value> {
+        match self {
+            Value::Map(map) if map.keys().all(|k| matches!(*k, Value::String(..))) => {
+                Ok(map.into_iter().map(|(k, v)| match k {
+                    Value::String(k) => (k, v),
+                    _ => unreachable!(),
+                }).collect())
+            },
+            v => Err(v),
+        }
+    }
+
+    /// Rebuilds the tree by applying `f` to every node bottom-up: the
+    /// children of a node, including map keys, are transformed before the
+    /// node itself is passed to `f`.
+    pub fn transform<F: FnMut(Value) -> Value>(self, mut f: F) -> Value {
+        self.transform_with(&mut f)
+    }
+
+    /// Removes redundant wrappers throughout the tree, including map keys,
+    /// to get a minimal tree for comparison. Exactly two rewrites are made:
+    ///
+    /// - `Newtype(x)` becomes `x`, as the newtype's name is not kept anyway.
+    /// - `Option(Some(Option(Some(x))))` becomes `Option(Some(x))`. A
+    ///   `Some(None)` is kept, as it differs from `None`.
+    ///
+    /// Map keys that become equal are merged, keeping one of their values.
+    pub fn simplify(&mut self) {
+        *self = mem::replace(self, Value::Unit).transform(|v| match v {
+            Value::Newtype(v) => *v,
+            Value::Option(Some(v)) => match *v {
+                v @ Value::Option(Some(..)) => v,
+                v => Value::Option(Some(Box::new(v))),
+            },
+            v => v,
+        });
+    }
+
+    fn transform_with<F: FnMut(Value) -> Value>(self, f: &mut F) -> Value {
+        let value = match self {
+            Value::Option(Some(v)) => Value::Option(Some(Box::new(v.transform_with(f)))),
+            Value::Newtype(v) => Value::Newtype(Box::new(v.transform_with(f))),
+            Value::Seq(v) => Value::Seq(v.into_iter().map(|v| v.transform_with(f)).collect()),
+            Value::Map(v) => Value::Map(v.into_iter().map(|(k, v)| (k.transform_with(f), v.transform_with(f))).collect()),
+            v => v,
+        };
+        f(value)
+    }
+
+    fn is_null(&self) -> bool {
+        matches!(*self, Value::Unit | Value::Option(None))
+    }
+
+    /// Recursively removes map entries whose value is `Unit` or
+    /// `Option(None)`. Null seq elements are kept unless `seqs` is set, as
+    /// removing them shifts the indices of the elements that follow.
+    pub fn strip_nulls(&mut self, seqs: bool) {
+        match *self {
+            Value::Option(Some(ref mut v)) | Value::Newtype(ref mut v) => v.strip_nulls(seqs),
+            Value::Seq(ref mut v) => {
+                if seqs {
+                    v.retain(|v| !v.is_null());
+                }
+                v.iter_mut().for_each(|v| v.strip_nulls(seqs));
+            },
+            Value::Map(ref mut v) => {
+                v.retain(|_, v| !v.is_null());
+                v.values_mut().for_each(|v| v.strip_nulls(seqs));
+            },
+            _ => (),
+        }
+    }
+
+    /// Applies an [RFC 7396](https://tools.ietf.org/html/rfc7396) JSON Merge
+    /// Patch to `self`.
+    ///
+    /// A map patch is merged into `self` key by key, replacing `self` with an
+    /// empty map first if it is not one: a `Unit` or `Option(None)` value
+    /// removes the key, and any other value is merged recursively. Any patch
+    /// that is not a map, including a seq, replaces `self` entirely.
+    pub fn apply_merge_patch(&mut self, patch: Value) {
+        let patch = match patch {
+            Value::Map(patch) => patch,
+            patch => return *self = patch,
+        };
+        if !matches!(*self, Value::Map(..)) {
+            *self = Value::Map(ValueMap::new());
+        }
+        if let Value::Map(ref mut map) = *self {
+            for (k, v) in patch {
+                if v.is_null() {
+                    map.remove(&k);
+                } else {
+                    map.entry(k).or_insert(Value::Unit).apply_merge_patch(v);
+                }
+            }
+        }
+    }
+
+    /// Looks up `key` in a map and deserializes its value, or returns a
+    /// `MissingKey` error if `self` is not a map containing `key`.
+    pub fn try_get<'de, T: Deserialize<'de>>(&'de self, key: &str) -> Result<T, DeserializerError> {
+        let value = match *self {
+            Value::Map(ref map) => map.get(&Value::String(key.into())),
+            _ => None,
+        };
+        match value {
+            Some(value) => T::deserialize(value),
+            None => Err(DeserializerError::MissingKey(key.into())),
+        }
+    }
+
+    /// Returns the contents of a `String`, or of `Bytes` with any invalid
+    /// UTF-8 replaced by `U+FFFD`.
+    pub fn as_string_lossy(&self) -> Option<Cow<'_, str>> {
+        match *self {
+            Value::String(ref v) => Some(Cow::Borrowed(v)),
+            Value::Bytes(ref v) => Some(String::from_utf8_lossy(v)),
+            _ => None,
+        }
+    }
+
+    /// Returns a `String` as-is, or a bool, number or char rendered as text.
+    /// Containers, bytes and `Unit` return `None`.
+    pub fn to_cow_str(&self) -> Option<Cow<'_, str>> {
+        match *self {
+            Value::String(ref v) => Some(Cow::Borrowed(v)),
+            ref v => de::scalar_to_string(v).map(Cow::Owned),
+        }
+    }
+
+    /// Joins the elements of a seq of strings with `sep`, or returns `None`
+    /// if `self` is not a `Seq` or any element is not a `String`.
+    pub fn join_strings(&self, sep: &str) -> Option<String> {
+        match *self {
+            Value::Seq(ref v) => v.iter()
+                .map(|v| match *v {
+                    Value::String(ref v) => Some(&v[..]),
+                    _ => None,
+                })
+                .collect::<Option<Vec<_>>>()
+                .map(|v| v.join(sep)),
+            _ => None,
+        }
+    }
+
+    /// Recursively replaces every `Bytes` in the tree, including map keys,
+    /// with a `String` decoded as by `as_string_lossy`.
+    pub fn bytes_to_string_lossy(&mut self) {
+        match *self {
+            Value::Bytes(ref v) => *self = Value::String(String::from_utf8_lossy(v).into_owned()),
+            Value::Option(Some(ref mut v)) | Value::Newtype(ref mut v) => v.bytes_to_string_lossy(),
+            Value::Seq(ref mut v) => v.iter_mut().for_each(Value::bytes_to_string_lossy),
+            Value::Map(ref mut v) => {
+                *v = mem::take(v).into_iter().map(|(mut k, mut v)| {
+                    k.bytes_to_string_lossy();
+                    v.bytes_to_string_lossy();
+                    (k, v)
+                }).collect();
+            },
+            _ => (),
+        }
+    }
+
+    /// Sorts the entries of every map in the tree by key.
+    ///
+    /// Maps are always sorted unless the `preserve_order` feature is
+    /// enabled, so this is a no-op without it.
+    pub fn sort_map_keys(&mut self) {
+        #[cfg(feature = "preserve_order")]
+        match *self {
+            Value::Option(Some(ref mut v)) | Value::Newtype(ref mut v) => v.sort_map_keys(),
+            Value::Seq(ref mut v) => v.iter_mut().for_each(Value::sort_map_keys),
+            Value::Map(ref mut v) => {
+                v.map.sort_keys();
+                *v = mem::take(v).into_iter().map(|(mut k, mut v)| {
+                    k.sort_map_keys();
+                    v.sort_map_keys();
+                    (k, v)
+                }).collect();
+            },
+            _ => (),
+        }
+    }
+
+    /// Reverses the order of the entries of this map. A no-op without the
+    /// `preserve_order` feature, or if `self` is not a map.
+    pub fn reverse_map(&mut self) {
+        #[cfg(feature = "preserve_order")]
+        {
+            if let Value::Map(ref mut v) = *self {
+                v.map.reverse();
+            }
+        }
+    }
+
+    /// Moves the entry for `key` to the front of this map, keeping the
+    /// relative order of the others. A no-op without the `preserve_order`
+    /// feature, or if `self` is not a map containing `key`.
+    pub fn move_key_to_front(&mut self, key: &Value) {
+        #[cfg(feature = "preserve_order")]
+        {
+            if let Value::Map(ref mut v) = *self {
+                if let Some(index) = v.map.get_index_of(key) {
+                    v.map.move_index(index, 0);
+                }
+            }
+        }
+        #[cfg(not(feature = "preserve_order"))]
+        let _ = key;
+    }
+
+    fn is_empty_container(&self) -> bool {
+        match *self {
+            Value::Seq(ref v) => v.is_empty(),
+            Value::Map(ref v) => v.is_empty(),
+            _ => false,
+        }
+    }
+
+    /// Recursively removes map entries and seq elements whose value is an
+    /// empty map or seq. Children are pruned first, so a container left
+    /// empty by pruning is removed from its parent too.
+    ///
+    /// `Unit` and other null values are kept; see `strip_nulls` for those.
+    pub fn prune_empty(&mut self) {
+        match *self {
+            Value::Option(Some(ref mut v)) | Value::Newtype(ref mut v) => v.prune_empty(),
+            Value::Seq(ref mut v) => {
+                v.iter_mut().for_each(Value::prune_empty);
+                v.retain(|v| !v.is_empty_container());
+            },
+            Value::Map(ref mut v) => {
+                v.values_mut().for_each(Value::prune_empty);
+                v.retain(|_, v| !v.is_empty_container());
+            },
+            _ => (),
+        }
+    }
+
+    fn as_float(&self) -> Option<f64> {
+        match *self {
+            Value::F32(v) => Some(v as f64),
+            Value::F64(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    fn as_i128(&self) -> Option<i128> {
+        match *self {
+            Value::U8(v) => Some(v.into()),
+            Value::U16(v) => Some(v.into()),
+            Value::U32(v) => Some(v.into()),
+            Value::U64(v) => Some(v.into()),
+            Value::I8(v) => Some(v.into()),
+            Value::I16(v) => Some(v.into()),
+            Value::I32(v) => Some(v.into()),
+            Value::I64(v) => Some(v.into()),
+            _ => None,
+        }
+    }
+
+    fn as_f64_lossy(&self) -> Option<f64> {
+        match *self {
+            Value::U8(v) => Some(v as f64),
+            Value::U16(v) => Some(v as f64),
+            Value::U32(v) => Some(v as f64),
+            Value::U64(v) => Some(v as f64),
+            Value::I8(v) => Some(v as f64),
+            Value::I16(v) => Some(v as f64),
+            Value::I32(v) => Some(v as f64),
+            Value::I64(v) => Some(v as f64),
+            _ => self.as_float(),
+        }
+    }
+}
+
+macro_rules! impl_from {
+    ($($ty:ty => $variant:ident,)*) => {
+        $(
+            impl From<$ty> for Value {
+                fn from(v: $ty) -> Value {
+                    Value::$variant(v)
+                }
+            }
+        )*
+    };
+}
+
+impl_from! {
+    bool => Bool,
+    u8 => U8,
+    u16 => U16,
+    u32 => U32,
+    u64 => U64,
+    i8 => I8,
+    i16 => I16,
+    i32 => I32,
+    i64 => I64,
+    f32 => F32,
+    f64 => F64,
+    char => Char,
+    String => String,
+    Vec<Value> => Seq,
+    ValueMap => Map,
+}
+
+impl From<BTreeMap<Value, Value>> for Value {
+    fn from(v: BTreeMap<Value, Value>) -> Value {
+        Value::Map(v.into_iter().collect())
+    }
+}
+
+impl<'a> From<&'a str> for Value {
+    fn from(v: &'a str) -> Value {
+        Value::String(v.into())
+    }
+}
+
+impl From<HashMap<String, Value>> for Value {
+    fn from(v: HashMap<String, Value>) -> Value {
+        Value::Map(v.into_iter().map(|(k, v)| (Value::String(k), v)).collect())
+    }
+}
+
+impl From<()> for Value {
+    fn from(_: ()) -> Value {
+        Value::Unit
+    }
+}
+
+/// The default value is `Value::Unit`.
+impl Default for Value {
+    fn default() -> Value {
+        Value::Unit
+    }
+}
+
+// inserts `value` under the path given by `segments`, replacing anything in
+// the way with a map
+fn insert_nested<'a, I: Iterator<Item = &'a str>>(root: &mut ValueMap, segments: I, value: Value) {
+    let mut segments = segments.peekable();
+    let mut map = root;
+    while let Some(segment) = segments.next() {
+        let entry = map.entry(Value::String(segment.into())).or_insert(Value::Unit);
+        if segments.peek().is_none() {
+            *entry = value;
+            return
+        }
+        if !matches!(*entry, Value::Map(..)) {
+            *entry = Value::Map(ValueMap::new());
+        }
+        map = match *entry {
+            Value::Map(ref mut map) => map,
+            _ => unreachable!(),
+        };
+    }
 }
 
 impl Eq for Value { }
@@ -382,3 +1225,2298 @@ fn deserialize_newtype2() {
     let bar = Bar::deserialize(input).unwrap();
     assert_eq!(bar, Bar { foo: Foo(5) });
 }
+
+#[test]
+fn non_string_keys() {
+    let value = Value::Map(vec![
+        (Value::I8(-1), Value::Unit),
+        (Value::String("a".into()), Value::Map(vec![
+            (Value::U32(1), Value::Unit),
+            (Value::String("b".into()), Value::Unit),
+        ].into_iter().collect())),
+        (Value::String("c".into()), Value::Seq(vec![
+            Value::Map(vec![
+                (Value::Bool(true), Value::Unit),
+            ].into_iter().collect()),
+        ])),
+    ].into_iter().collect());
+
+    assert_eq!(value.non_string_keys(), vec![
+        vec![PathSegment::Key(Value::I8(-1))],
+        vec![PathSegment::Key(Value::String("a".into())), PathSegment::Key(Value::U32(1))],
+        vec![
+            PathSegment::Key(Value::String("c".into())),
+            PathSegment::Index(0),
+            PathSegment::Key(Value::Bool(true)),
+        ],
+    ]);
+
+    let value = Value::Map(vec![
+        (Value::String("a".into()), Value::U8(1)),
+    ].into_iter().collect());
+    assert!(value.non_string_keys().is_empty());
+}
+
+#[test]
+fn deserialize_transparent() {
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    #[serde(transparent)]
+    struct Foo {
+        inner: u32,
+    }
+
+    let input = Value::U32(5);
+    let foo = Foo::deserialize(input.clone()).unwrap();
+    assert_eq!(foo, Foo { inner: 5 });
+    assert_eq!(to_value(&foo).unwrap(), input);
+}
+
+#[test]
+fn deserialize_newtype_mismatch() {
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Foo(u32);
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Bar {
+        foo: u32,
+    }
+
+    let input = Value::Newtype(Box::new(Value::U32(5)));
+    assert_eq!(u32::deserialize(input.clone()).unwrap(), 5);
+    assert_eq!(Foo::deserialize(input).unwrap(), Foo(5));
+
+    let input = Value::U32(5);
+    assert_eq!(Foo::deserialize(input).unwrap(), Foo(5));
+
+    let input = Value::Newtype(Box::new(Value::Map(vec![
+        (Value::String("foo".into()), Value::Newtype(Box::new(Value::U32(5)))),
+    ].into_iter().collect())));
+    assert_eq!(Bar::deserialize(input).unwrap(), Bar { foo: 5 });
+}
+
+#[test]
+fn approx_eq() {
+    let a = Value::Seq(vec![Value::F64(1.0), Value::String("a".into())]);
+    let b = Value::Seq(vec![Value::F64(1.0000001), Value::String("a".into())]);
+    assert!(a != b);
+    assert!(a.approx_eq(&b, 1e-6));
+    assert!(!a.approx_eq(&b, 1e-9));
+
+    let c = Value::Seq(vec![Value::F64(1.0), Value::String("b".into())]);
+    assert!(!a.approx_eq(&c, 1e-6));
+
+    let d = Value::Seq(vec![Value::U8(1), Value::String("a".into())]);
+    assert!(!a.approx_eq(&d, 1e-6));
+    assert!(a.approx_eq_numeric(&d, 1e-6));
+    assert!(b.approx_eq_numeric(&d, 1e-6));
+}
+
+#[test]
+fn serialize_human_readable() {
+    struct Addr([u8; 4]);
+
+    impl serde::Serialize for Addr {
+        fn serialize<S: serde::Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+            if s.is_human_readable() {
+                let [a, b, c, d] = self.0;
+                s.serialize_str(&format!("{}.{}.{}.{}", a, b, c, d))
+            } else {
+                s.serialize_bytes(&self.0)
+            }
+        }
+    }
+
+    let addrs = vec![Some(Addr([127, 0, 0, 1]))];
+    assert_eq!(
+        to_value(&addrs).unwrap(),
+        Value::Seq(vec![Value::Option(Some(Box::new(Value::String("127.0.0.1".into()))))])
+    );
+    assert_eq!(
+        to_value(HumanReadable::new(&addrs, false)).unwrap(),
+        Value::Seq(vec![Value::Option(Some(Box::new(Value::Bytes(vec![127, 0, 0, 1]))))])
+    );
+
+    let value = to_value(HumanReadable::new(&addrs, false)).unwrap();
+    assert_eq!(to_value(value.serialize_as(true)).unwrap(), value);
+}
+
+#[cfg(feature = "json")]
+#[test]
+fn human_readable_forwards_128_bit_ints() {
+    assert_eq!(serde_json::to_string(&HumanReadable::new(&u128::MAX, false)).unwrap(), u128::MAX.to_string());
+    assert_eq!(serde_json::to_string(&HumanReadable::new(&i128::MIN, true)).unwrap(), i128::MIN.to_string());
+}
+
+#[test]
+fn deserialize_enum_from_index() {
+    #[derive(Deserialize, Debug, PartialEq, Eq)]
+    enum Foo {
+        Bar,
+        Baz,
+    }
+
+    let value = Value::U32(1);
+    Foo::deserialize(value.clone()).expect_err("enum_from_index should be opt-in");
+
+    let de = ValueDeserializer::<DeserializerError>::new(value).enum_from_index(true);
+    assert_eq!(Foo::deserialize(de).unwrap(), Foo::Baz);
+
+    let value = Value::Seq(vec![Value::U8(0), Value::U64(1)]);
+    let de = ValueDeserializer::<DeserializerError>::new(value).enum_from_index(true);
+    assert_eq!(Vec::<Foo>::deserialize(de).unwrap(), vec![Foo::Bar, Foo::Baz]);
+}
+
+#[test]
+fn node_count_and_depth() {
+    assert_eq!(Value::Unit.node_count(), 1);
+    assert_eq!(Value::Unit.depth(), 1);
+    assert_eq!(Value::Seq(vec![]).depth(), 1);
+
+    let value = Value::Map(vec![
+        (Value::String("a".into()), Value::Seq(vec![
+            Value::U8(1),
+            Value::Option(Some(Box::new(Value::U8(2)))),
+        ])),
+        (Value::String("b".into()), Value::Bool(true)),
+    ].into_iter().collect());
+    assert_eq!(value.node_count(), 8);
+    assert_eq!(value.depth(), 4);
+}
+
+#[test]
+fn deserialize_max_depth() {
+    #[derive(Deserialize, Debug)]
+    struct Node {
+        #[allow(dead_code)]
+        children: Vec<Node>,
+    }
+
+    let mut value = Value::Map(vec![
+        (Value::String("children".into()), Value::Seq(vec![])),
+    ].into_iter().collect());
+    for _ in 0..8 {
+        value = Value::Map(vec![
+            (Value::String("children".into()), Value::Seq(vec![value])),
+        ].into_iter().collect());
+    }
+
+    Node::deserialize(value.clone()).unwrap();
+    Node::deserialize(ValueDeserializer::<DeserializerError>::new(value.clone()).max_depth(18)).unwrap();
+    match Node::deserialize(ValueDeserializer::<DeserializerError>::new(value).max_depth(4)) {
+        Err(DeserializerError::Custom(msg)) => assert_eq!(msg, "recursion limit of 4 exceeded"),
+        res => panic!("unexpected result {:?}", res),
+    }
+}
+
+#[test]
+fn deserialize_max_depth_enums_and_newtypes() {
+    #[derive(Serialize, Deserialize, Debug)]
+    enum List {
+        Nil,
+        Cons(Box<List>),
+    }
+
+    #[derive(Serialize, Deserialize, Debug)]
+    struct Wrap(Option<Box<Wrap>>);
+
+    let mut list = List::Nil;
+    let mut wrap = Wrap(None);
+    for _ in 0..50 {
+        list = List::Cons(Box::new(list));
+        wrap = Wrap(Some(Box::new(wrap)));
+    }
+    let list = to_value(list).unwrap();
+    let wrap = to_value(wrap).unwrap();
+
+    List::deserialize(ValueDeserializer::<DeserializerError>::new(list.clone()).max_depth(60)).unwrap();
+    match List::deserialize(ValueDeserializer::<DeserializerError>::new(list.clone()).max_depth(5)) {
+        Err(DeserializerError::Custom(msg)) => assert_eq!(msg, "recursion limit of 5 exceeded"),
+        res => panic!("unexpected result {:?}", res),
+    }
+    assert!(List::deserialize(ValueRefDeserializer::<DeserializerError>::new(&list).max_depth(5)).is_err());
+
+    Wrap::deserialize(ValueDeserializer::<DeserializerError>::new(wrap.clone()).max_depth(110)).unwrap();
+    match Wrap::deserialize(ValueDeserializer::<DeserializerError>::new(wrap.clone()).max_depth(5)) {
+        Err(DeserializerError::Custom(msg)) => assert_eq!(msg, "recursion limit of 5 exceeded"),
+        res => panic!("unexpected result {:?}", res),
+    }
+    assert!(Wrap::deserialize(ValueRefDeserializer::<DeserializerError>::new(&wrap).max_depth(5)).is_err());
+
+    // transparently unwrapped newtypes count too
+    let mut value = Value::U8(1);
+    for _ in 0..10 {
+        value = Value::Newtype(Box::new(value));
+    }
+    assert!(u8::deserialize(ValueDeserializer::<DeserializerError>::new(value.clone()).max_depth(5)).is_err());
+    assert_eq!(u8::deserialize(ValueDeserializer::<DeserializerError>::new(value).max_depth(10)).unwrap(), 1);
+}
+
+#[cfg(feature = "toml")]
+#[test]
+fn toml_round_trip() {
+    use std::convert::TryFrom;
+
+    let input: ::toml::Value = r#"
+        title = "example"
+        enabled = true
+        ratio = 0.5
+        released = 1979-05-27T07:32:00Z
+
+        [server]
+        ports = [8000, 8001]
+    "#.parse().unwrap();
+
+    let value = Value::from(input.clone());
+    assert_eq!(value, Value::Map(vec![
+        (Value::String("title".into()), Value::String("example".into())),
+        (Value::String("enabled".into()), Value::Bool(true)),
+        (Value::String("ratio".into()), Value::F64(0.5)),
+        (Value::String("released".into()), Value::String("1979-05-27T07:32:00Z".into())),
+        (Value::String("server".into()), Value::Map(vec![
+            (Value::String("ports".into()), Value::Seq(vec![Value::I64(8000), Value::I64(8001)])),
+        ].into_iter().collect())),
+    ].into_iter().collect()));
+
+    let output: ::toml::Value = TryFrom::try_from(value.clone()).unwrap();
+    assert_eq!(Value::from(output), value);
+
+    let value = Value::Map(vec![
+        (Value::U8(1), Value::Bool(true)),
+    ].into_iter().collect());
+    match <::toml::Value as TryFrom<_>>::try_from(value) {
+        Err(SerializerError::KeyMustBeString(key)) => assert_eq!(key, Value::U8(1)),
+        res => panic!("unexpected result {:?}", res),
+    }
+}
+
+#[cfg(feature = "cbor")]
+#[test]
+fn cbor_round_trip() {
+    use std::convert::TryFrom;
+    use ciborium::value::{Integer, Value as CborValue};
+
+    let input = CborValue::Map(vec![
+        (CborValue::Integer(1.into()), CborValue::Bytes(b"hi".to_vec())),
+        (CborValue::Integer((-300).into()), CborValue::Array(vec![
+            CborValue::Integer(70000.into()),
+            CborValue::Null,
+        ])),
+        (CborValue::Text("tagged".into()), CborValue::Tag(0, Box::new(CborValue::Text("2020-01-01".into())))),
+    ]);
+
+    let value = Value::try_from(input).unwrap();
+    assert_eq!(value, Value::Map(vec![
+        (Value::U8(1), Value::Bytes(b"hi".to_vec())),
+        (Value::I16(-300), Value::Seq(vec![Value::U32(70000), Value::Unit])),
+        (Value::String("tagged".into()), Value::Newtype(Box::new(Value::String("2020-01-01".into())))),
+    ].into_iter().collect()));
+
+    let output = CborValue::from(value.clone());
+    let value = Value::Map(vec![
+        (Value::U8(1), Value::Bytes(b"hi".to_vec())),
+        (Value::I16(-300), Value::Seq(vec![Value::U32(70000), Value::Unit])),
+        (Value::String("tagged".into()), Value::String("2020-01-01".into())),
+    ].into_iter().collect());
+    assert_eq!(Value::try_from(output).unwrap(), value);
+
+    let too_small = CborValue::Integer(Integer::try_from(i128::from(i64::MIN) - 1).unwrap());
+    assert!(Value::try_from(too_small).is_err());
+    let nested = CborValue::Array(vec![CborValue::Integer(Integer::try_from(-(1i128 << 64)).unwrap())]);
+    assert!(Value::try_from(nested).is_err());
+}
+
+#[test]
+fn deserialize_borrowed_cow_str() {
+    use std::borrow::Cow;
+
+    #[derive(Deserialize)]
+    struct Foo<'a> {
+        #[serde(borrow)]
+        s: Cow<'a, str>,
+    }
+
+    let value = Value::Map(vec![
+        (Value::String("s".into()), Value::String("hello".into())),
+    ].into_iter().collect());
+
+    let foo = Foo::deserialize(&value).unwrap();
+    match foo.s {
+        Cow::Borrowed(s) => assert_eq!(s, "hello"),
+        Cow::Owned(..) => panic!("expected a borrowed str"),
+    }
+
+    let foo = Foo::deserialize(value).unwrap();
+    match foo.s {
+        Cow::Owned(s) => assert_eq!(s, "hello"),
+        Cow::Borrowed(..) => panic!("expected an owned str"),
+    }
+}
+
+#[test]
+fn from_deserializer_narrows_integers() {
+    let input = Value::Seq(vec![
+        Value::U64(1),
+        Value::U64(300),
+        Value::U64(70000),
+        Value::U64(1 << 40),
+        Value::I64(-1),
+        Value::I64(300),
+    ]);
+    assert_eq!(Value::from_deserializer(input).unwrap(), Value::Seq(vec![
+        Value::U8(1),
+        Value::U16(300),
+        Value::U32(70000),
+        Value::U64(1 << 40),
+        Value::I8(-1),
+        Value::I16(300),
+    ]));
+}
+
+#[cfg(feature = "json")]
+#[test]
+fn from_json() {
+    let input = br#"{"name": "example", "ports": [80, 65536], "offset": -5, "ratio": 0.5, "extra": null}"#;
+    let expected = Value::Map(vec![
+        (Value::String("name".into()), Value::String("example".into())),
+        (Value::String("ports".into()), Value::Seq(vec![Value::U8(80), Value::U32(65536)])),
+        (Value::String("offset".into()), Value::I8(-5)),
+        (Value::String("ratio".into()), Value::F64(0.5)),
+        (Value::String("extra".into()), Value::Unit),
+    ].into_iter().collect());
+
+    assert_eq!(Value::from_json_slice(input).unwrap(), expected);
+    assert_eq!(Value::from_json_reader(&input[..]).unwrap(), expected);
+    Value::from_json_slice(b"{} {}").expect_err("trailing data should be rejected");
+}
+
+#[test]
+fn pointer_set() {
+    let mut value = Value::Map(vec![
+        (Value::String("a".into()), Value::Seq(vec![
+            Value::U8(1),
+            Value::Map(vec![
+                (Value::String("b/c".into()), Value::Bool(false)),
+            ].into_iter().collect()),
+        ])),
+    ].into_iter().collect());
+
+    assert_eq!(value.pointer("/a/0"), Some(&Value::U8(1)));
+    assert_eq!(value.pointer("/a/1/b~1c"), Some(&Value::Bool(false)));
+    assert_eq!(value.pointer("/a/01"), None);
+    assert_eq!(value.pointer(""), Some(&value));
+
+    value.set("/a/1/b~1c", Value::Bool(true), false).unwrap();
+    assert_eq!(value.pointer("/a/1/b~1c"), Some(&Value::Bool(true)));
+    value.set("/a/-", Value::U8(2), false).unwrap();
+    assert_eq!(value.pointer("/a/2"), Some(&Value::U8(2)));
+
+    assert_eq!(value.set("/x/y/z", Value::Unit, false), Err(PathError::Missing("x".into())));
+    assert_eq!(value.pointer("/x"), None);
+    value.set("/x/y/z", Value::Unit, true).unwrap();
+    assert_eq!(value.pointer("/x/y/z"), Some(&Value::Unit));
+    assert_eq!(value.pointer("/x"), Some(&Value::Map(vec![
+        (Value::String("y".into()), Value::Map(vec![
+            (Value::String("z".into()), Value::Unit),
+        ].into_iter().collect())),
+    ].into_iter().collect())));
+
+    assert_eq!(value.set("/a/0/b", Value::Unit, true), Err(PathError::NotContainer("b".into())));
+    assert_eq!(value.set("a", Value::Unit, true), Err(PathError::InvalidPointer("a".into())));
+}
+
+#[test]
+fn pointer_remove() {
+    let mut value = Value::Map(vec![
+        (Value::String("a".into()), Value::Seq(vec![Value::U8(1), Value::U8(2), Value::U8(3)])),
+        (Value::String("b".into()), Value::Bool(true)),
+        (Value::String("c".into()), Value::Bool(false)),
+    ].into_iter().collect());
+
+    assert_eq!(value.remove("/b"), Some(Value::Bool(true)));
+    assert_eq!(value.remove("/b"), None);
+    assert_eq!(value.remove("/a/1"), Some(Value::U8(2)));
+    assert_eq!(value.remove("/a/2"), None);
+    assert_eq!(value, Value::Map(vec![
+        (Value::String("a".into()), Value::Seq(vec![Value::U8(1), Value::U8(3)])),
+        (Value::String("c".into()), Value::Bool(false)),
+    ].into_iter().collect()));
+
+    let root = value.clone();
+    assert_eq!(value.remove(""), Some(root));
+    assert_eq!(value, Value::Unit);
+
+    let mut value = Value::map_builder().entry("z", 1u8).entry("a", 2u8).entry("m", 3u8).build();
+    assert_eq!(value.remove("/a"), Some(Value::U8(2)));
+    let keys: Vec<_> = match value {
+        Value::Map(ref map) => map.keys().cloned().collect(),
+        _ => unreachable!(),
+    };
+    #[cfg(feature = "preserve_order")]
+    assert_eq!(keys, [Value::String("z".into()), Value::String("m".into())]);
+    #[cfg(not(feature = "preserve_order"))]
+    assert_eq!(keys, [Value::String("m".into()), Value::String("z".into())]);
+}
+
+#[test]
+fn get_or_insert_with() {
+    let mut value = Value::Map(ValueMap::new());
+    *value.get_or_insert_with("a", || Value::U8(1)).unwrap() = Value::U8(2);
+    assert_eq!(value.get_or_insert_with("a", || Value::U8(1)), Some(&mut Value::U8(2)));
+    assert_eq!(value.get_or_insert_with(3u32, || Value::Unit), Some(&mut Value::Unit));
+    assert_eq!(value, Value::Map(vec![
+        (Value::U32(3), Value::Unit),
+        (Value::String("a".into()), Value::U8(2)),
+    ].into_iter().collect()));
+
+    assert_eq!(Value::Unit.get_or_insert_with("a", || Value::Unit), None);
+}
+
+#[test]
+fn serializer_errors() {
+    use serde::ser::{Error, SerializeMap};
+
+    struct Failing;
+
+    impl serde::Serialize for Failing {
+        fn serialize<S: serde::Serializer>(&self, _: S) -> Result<S::Ok, S::Error> {
+            Err(S::Error::custom("failing"))
+        }
+    }
+
+    struct ValueWithoutKey;
+
+    impl serde::Serialize for ValueWithoutKey {
+        fn serialize<S: serde::Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+            let mut map = s.serialize_map(None)?;
+            map.serialize_value(&1u8)?;
+            map.end()
+        }
+    }
+
+    match to_value(vec![Failing]) {
+        Err(SerializerError::Custom(msg)) => assert_eq!(msg, "failing"),
+        res => panic!("unexpected result {:?}", res),
+    }
+
+    match to_value(ValueWithoutKey) {
+        Err(SerializerError::MissingKey) => (),
+        res => panic!("unexpected result {:?}", res),
+    }
+}
+
+#[test]
+fn deserialize_collecting_errors() {
+    #[derive(Deserialize, Debug)]
+    #[serde(deny_unknown_fields)]
+    #[allow(dead_code)]
+    struct Config {
+        name: String,
+        port: u16,
+    }
+
+    let mut map = ValueMap::new();
+    map.insert(Value::String("name".into()), Value::String("foo".into()));
+    map.insert(Value::String("port".into()), Value::U16(80));
+    map.insert(Value::String("colour".into()), Value::Bool(true));
+    map.insert(Value::String("host".into()), Value::Unit);
+
+    match Value::Map(map.clone()).deserialize_into_collecting::<Config>() {
+        Err(DeserializerError::Multiple(errors)) => {
+            let fields: Vec<_> = errors.iter().map(|e| match *e {
+                DeserializerError::UnknownField(ref field, _) => field.clone(),
+                ref e => panic!("unexpected error {:?}", e),
+            }).collect();
+            assert_eq!(fields, ["colour", "host"]);
+        },
+        res => panic!("unexpected result {:?}", res),
+    }
+
+    map.insert(Value::String("port".into()), Value::String("80".into()));
+    match Value::Map(map).deserialize_into_collecting::<Config>() {
+        Err(DeserializerError::Multiple(errors)) => {
+            assert_eq!(errors.len(), 3);
+            assert!(matches!(errors[0], DeserializerError::InvalidType(..)), "{:?}", errors[0]);
+            let fields: Vec<_> = errors[1..].iter().map(|e| match *e {
+                DeserializerError::UnknownField(ref field, _) => field.clone(),
+                ref e => panic!("unexpected error {:?}", e),
+            }).collect();
+            assert_eq!(fields, ["colour", "host"]);
+        },
+        res => panic!("unexpected result {:?}", res),
+    }
+}
+
+#[test]
+fn deserialize_collecting_errors_parse_map_keys() {
+    #[derive(Deserialize, Debug, PartialEq)]
+    #[serde(deny_unknown_fields)]
+    struct Ports<'a> {
+        name: &'a str,
+        ports: HashMap<u16, String>,
+    }
+
+    let value = Value::map_builder()
+        .entry("name", "web")
+        .entry("ports", Value::map_builder().entry("80", "http").entry("443", "https"))
+        .build();
+    let expected = Ports {
+        name: "web",
+        ports: vec![(80, "http".to_owned()), (443, "https".to_owned())].into_iter().collect(),
+    };
+    let de = ValueRefDeserializer::<DeserializerError>::new(&value).collect_errors(true).parse_map_keys(true);
+    assert_eq!(Ports::deserialize(de).unwrap(), expected);
+
+    let mut value = value;
+    if let Value::Map(ref mut map) = value {
+        map.insert(Value::String("colour".into()), Value::Bool(true));
+        map.insert(Value::String("host".into()), Value::Unit);
+    }
+    let de = ValueRefDeserializer::<DeserializerError>::new(&value).collect_errors(true).parse_map_keys(true);
+    let message = Ports::deserialize(de).unwrap_err().to_string();
+    assert!(message.contains("colour") && message.contains("host"), "{}", message);
+}
+
+#[test]
+fn coalesce() {
+    let values = vec![Value::Unit, Value::Option(None), Value::U8(1), Value::U8(2)];
+    assert_eq!(Value::coalesce(values), Value::U8(1));
+    assert_eq!(Value::coalesce(vec![Value::Option(None)]), Value::Unit);
+}
+
+#[test]
+fn expect_kind() {
+    let value = Value::Map(ValueMap::new());
+    assert_eq!(value.expect_kind(ValueKind::Map).unwrap(), &value);
+    assert!(Value::I16(-1).expect_kind(ValueKind::Number).is_ok());
+
+    match Value::Seq(vec![]).expect_kind(ValueKind::Map) {
+        Err(DeserializerError::InvalidType(Unexpected::Seq, ref exp)) => assert_eq!(exp, "a map"),
+        res => panic!("unexpected result {:?}", res),
+    }
+}
+
+#[test]
+fn deserialize_bool_from_int() {
+    let de = |v| ValueDeserializer::<DeserializerError>::new(v).coerce_bool_from_int(true);
+    assert!(bool::deserialize(de(Value::U8(1))).unwrap());
+    assert!(!bool::deserialize(de(Value::I64(0))).unwrap());
+    assert!(bool::deserialize(de(Value::U8(2))).is_err());
+    assert!(bool::deserialize(Value::U8(1)).is_err());
+}
+
+#[test]
+fn deserialize_renamed_fields() {
+    #[derive(Deserialize, Debug, PartialEq)]
+    #[serde(rename_all = "camelCase")]
+    struct Config {
+        max_connections: u32,
+    }
+
+    let mut map = ValueMap::new();
+    map.insert(Value::String("maxConnections".into()), Value::U32(8));
+    let expected = Config { max_connections: 8 };
+    assert_eq!(Config::deserialize(&Value::Map(map.clone())).unwrap(), expected);
+    assert_eq!(Value::Map(map).deserialize_into::<Config>().unwrap(), expected);
+}
+
+#[test]
+fn seq_to_map() {
+    let entry = |k: &str, v: u8| Value::Map(vec![(k.into(), v.into())].into_iter().collect());
+    let seq = Value::Seq(vec![entry("a", 1), entry("b", 2)]);
+    let expected = Value::Map(vec![("a".into(), 1u8.into()), ("b".into(), 2u8.into())].into_iter().collect());
+    assert_eq!(seq.seq_to_map(), Some(expected));
+
+    let seq = Value::Seq(vec![entry("a", 1), entry("a", 2)]);
+    assert_eq!(seq.seq_to_map(), Some(entry("a", 2)));
+    assert_eq!(Value::Seq(vec![Value::Unit]).seq_to_map(), None);
+}
+
+#[test]
+fn partition_map() {
+    let map: ValueMap = vec![("x-a", 1u8), ("x-b", 2), ("c", 3)].into_iter()
+        .map(|(k, v)| (k.into(), v.into()))
+        .collect();
+    let (known, unknown) = Value::Map(map).partition_map(|k, _| match *k {
+        Value::String(ref k) => k.starts_with("x-"),
+        _ => false,
+    });
+    assert_eq!(known, Value::Map(vec![("x-a".into(), 1u8.into()), ("x-b".into(), 2u8.into())].into_iter().collect()));
+    assert_eq!(unknown, Value::Map(vec![("c".into(), 3u8.into())].into_iter().collect()));
+
+    assert_eq!(Value::Unit.partition_map(|_, _| true), (Value::Unit, Value::Map(ValueMap::new())));
+}
+
+#[test]
+fn string_map() {
+    let mut map = HashMap::new();
+    map.insert("a".to_owned(), Value::U8(1));
+    map.insert("b".to_owned(), Value::Unit);
+    let value = Value::from(map.clone());
+    assert_eq!(value, Value::Map(vec![("a".into(), 1u8.into()), ("b".into(), ().into())].into_iter().collect()));
+    assert_eq!(value.into_string_map(), Ok(map));
+
+    let value = Value::Map(vec![(Value::U8(1), Value::Unit)].into_iter().collect());
+    assert_eq!(value.clone().into_string_map(), Err(value));
+}
+
+#[test]
+fn transform() {
+    let x = || Value::String("x".into());
+    let value = Value::Seq(vec![
+        x(),
+        Value::Option(Some(Box::new(x()))),
+        Value::Map(vec![(x(), Value::Newtype(Box::new(x())))].into_iter().collect()),
+    ]);
+    let expected = Value::Seq(vec![
+        Value::U8(0),
+        Value::Option(Some(Box::new(Value::U8(0)))),
+        Value::Map(vec![(Value::U8(0), Value::Newtype(Box::new(Value::U8(0))))].into_iter().collect()),
+    ]);
+    assert_eq!(value.transform(|v| if v == x() { Value::U8(0) } else { v }), expected);
+
+    let mut order = Vec::new();
+    Value::Seq(vec![Value::Unit]).transform(|v| {
+        order.push(v.clone());
+        v
+    });
+    assert_eq!(order, [Value::Unit, Value::Seq(vec![Value::Unit])]);
+}
+
+#[test]
+fn strip_nulls() {
+    let map = |entries: Vec<(&str, Value)>| Value::Map(entries.into_iter().map(|(k, v)| (k.into(), v)).collect());
+    let value = map(vec![
+        ("a", Value::Unit),
+        ("b", Value::Option(None)),
+        ("c", Value::Seq(vec![Value::Unit, map(vec![("d", Value::Unit), ("e", Value::U8(1))])])),
+    ]);
+
+    let mut stripped = value.clone();
+    stripped.strip_nulls(false);
+    assert_eq!(stripped, map(vec![
+        ("c", Value::Seq(vec![Value::Unit, map(vec![("e", Value::U8(1))])])),
+    ]));
+
+    let mut stripped = value;
+    stripped.strip_nulls(true);
+    assert_eq!(stripped, map(vec![
+        ("c", Value::Seq(vec![map(vec![("e", Value::U8(1))])])),
+    ]));
+}
+
+#[test]
+fn try_get() {
+    let value = Value::Map(vec![("port".into(), Value::U16(8080))].into_iter().collect());
+    assert_eq!(value.try_get::<u16>("port").unwrap(), 8080);
+
+    let key = String::from("host");
+    match value.try_get::<u16>(&key) {
+        Err(DeserializerError::MissingKey(ref key)) if key == "host" => (),
+        res => panic!("unexpected result {:?}", res),
+    }
+}
+
+#[test]
+fn deserialize_flatten_struct() {
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Inner {
+        port: u16,
+        host: String,
+    }
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Outer {
+        name: String,
+        #[serde(flatten)]
+        inner: Inner,
+    }
+
+    let value = Value::Map(vec![
+        ("name".into(), "foo".into()),
+        ("port".into(), Value::U16(80)),
+        ("host".into(), "localhost".into()),
+    ].into_iter().collect());
+    let expected = Outer {
+        name: "foo".into(),
+        inner: Inner {
+            port: 80,
+            host: "localhost".into(),
+        },
+    };
+    assert_eq!(Outer::deserialize(&value).unwrap(), expected);
+    assert_eq!(value.deserialize_into::<Outer>().unwrap(), expected);
+}
+
+#[cfg(feature = "json")]
+#[test]
+fn to_json_writer() {
+    let value = Value::Map(vec![
+        (Value::String("name".into()), Value::String("example".into())),
+        (Value::String("ports".into()), Value::Seq(vec![Value::U8(80), Value::U32(65536)])),
+    ].into_iter().collect());
+
+    let mut buf = Vec::new();
+    value.to_json_writer(&mut buf).unwrap();
+    assert_eq!(Value::from_json_slice(&buf).unwrap(), value);
+
+    let value = Value::Map(vec![(Value::Seq(vec![]), Value::Unit)].into_iter().collect());
+    assert!(value.to_json_writer(Vec::new()).is_err());
+}
+
+#[test]
+fn bytes_to_string_lossy() {
+    assert_eq!(Value::Bytes(b"abc".to_vec()).as_string_lossy().unwrap(), "abc");
+    assert_eq!(Value::Bytes(b"a\xffc".to_vec()).as_string_lossy().unwrap(), "a\u{fffd}c");
+    assert_eq!(Value::U8(0).as_string_lossy(), None);
+
+    let mut value = Value::Map(vec![
+        (Value::Bytes(b"key".to_vec()), Value::Seq(vec![Value::Bytes(b"a\xffc".to_vec())])),
+    ].into_iter().collect());
+    value.bytes_to_string_lossy();
+    assert_eq!(value, Value::Map(vec![
+        (Value::String("key".into()), Value::Seq(vec![Value::String("a\u{fffd}c".into())])),
+    ].into_iter().collect()));
+}
+
+#[cfg(feature = "preserve_order")]
+#[test]
+fn map_ordering() {
+    let keys = |v: &Value| match *v {
+        Value::Map(ref v) => v.keys().cloned().collect::<Vec<_>>(),
+        _ => unreachable!(),
+    };
+    let mut value = Value::Map(vec![
+        ("b".into(), Value::Unit),
+        ("c".into(), Value::Map(vec![("z".into(), Value::Unit), ("y".into(), Value::Unit)].into_iter().collect())),
+        ("a".into(), Value::Unit),
+    ].into_iter().collect());
+    assert_eq!(keys(&value), [Value::from("b"), "c".into(), "a".into()]);
+
+    value.move_key_to_front(&"a".into());
+    assert_eq!(keys(&value), [Value::from("a"), "b".into(), "c".into()]);
+
+    value.reverse_map();
+    assert_eq!(keys(&value), [Value::from("c"), "b".into(), "a".into()]);
+
+    value.sort_map_keys();
+    assert_eq!(keys(&value), [Value::from("a"), "b".into(), "c".into()]);
+    assert_eq!(keys(value.pointer("/c").unwrap()), [Value::from("y"), "z".into()]);
+}
+
+#[test]
+fn deserialize_ignores_nested_unknown_field() {
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Config {
+        #[serde(skip)]
+        skipped: u8,
+        name: String,
+    }
+
+    let value = Value::Map(vec![
+        ("extra".into(), Value::Map(vec![
+            ("inner".into(), Value::Seq(vec![Value::Map(vec![("name".into(), "wrong".into())].into_iter().collect())])),
+        ].into_iter().collect())),
+        ("name".into(), "right".into()),
+        ("skipped".into(), Value::Seq(vec![Value::U8(1)])),
+    ].into_iter().collect());
+    let expected = Config {
+        skipped: 0,
+        name: "right".into(),
+    };
+    assert_eq!(Config::deserialize(&value).unwrap(), expected);
+    assert_eq!(value.deserialize_into::<Config>().unwrap(), expected);
+}
+
+#[test]
+fn deserialize_integer_widening() {
+    assert_eq!(i64::deserialize(Value::U32(5)).unwrap(), 5);
+    assert_eq!(u64::deserialize(Value::I64(5)).unwrap(), 5);
+    assert_eq!(u8::deserialize(&Value::I16(255)).unwrap(), 255);
+    assert!(u64::deserialize(Value::I32(-1)).is_err());
+    assert!(i8::deserialize(Value::U64(128)).is_err());
+    assert!(u8::deserialize(Value::Newtype(Box::new(Value::I8(-1)))).is_err());
+}
+
+#[test]
+fn builders() {
+    let value = Value::map_builder()
+        .entry("a", 1u32)
+        .entry("b", true)
+        .entry("c", Value::seq_builder().push(1u8).push("x"))
+        .build();
+    let expected = Value::Map(vec![
+        ("a".into(), Value::U32(1)),
+        ("b".into(), Value::Bool(true)),
+        ("c".into(), Value::Seq(vec![Value::U8(1), Value::String("x".into())])),
+    ].into_iter().collect());
+    assert_eq!(value, expected);
+}
+
+#[test]
+fn deserialize_tuple_length() {
+    let value = Value::Seq(vec![Value::U8(1), Value::String("a".into())]);
+    assert_eq!(<(u8, String)>::deserialize(&value).unwrap(), (1, "a".into()));
+    assert_eq!(value.deserialize_into::<(u8, String)>().unwrap(), (1, "a".into()));
+
+    match <(u8, String)>::deserialize(Value::Seq(vec![Value::U8(1)])) {
+        Err(DeserializerError::InvalidLength(1, _)) => (),
+        res => panic!("unexpected result {:?}", res),
+    }
+
+    let value = Value::Seq(vec![Value::U8(1), Value::String("a".into()), Value::Unit]);
+    match <(u8, String)>::deserialize(&value) {
+        Err(DeserializerError::InvalidLength(3, ref exp)) => assert_eq!(exp, "2 elements in sequence"),
+        res => panic!("unexpected result {:?}", res),
+    }
+    assert!(value.deserialize_into::<(u8, String)>().is_err());
+}
+
+#[test]
+fn concat_bytes() {
+    let seq = Value::Seq(vec![Value::Bytes(b"ab".to_vec()), Value::Bytes(vec![]), Value::Bytes(vec![7; 200])]);
+    let mut expected = b"ab".to_vec();
+    expected.extend(vec![7; 200]);
+    assert_eq!(seq.concat_bytes(), Some(Value::Bytes(expected)));
+
+    let prefixed = seq.concat_bytes_with_lengths().unwrap();
+    match prefixed {
+        Value::Bytes(ref v) => assert_eq!(v[..5], [2, b'a', b'b', 0, 0xc8]),
+        _ => panic!("expected bytes"),
+    }
+    assert_eq!(prefixed.split_bytes_with_lengths(), Some(seq));
+
+    assert_eq!(Value::Seq(vec![Value::U8(1)]).concat_bytes(), None);
+    assert_eq!(Value::Bytes(vec![3, 1]).split_bytes_with_lengths(), None);
+}
+
+#[test]
+fn deserialize_smart_pointers() {
+    use std::rc::Rc;
+    use std::sync::Arc;
+
+    assert_eq!(Value::U32(5).deserialize_into::<Box<u32>>().unwrap(), Box::new(5));
+    assert_eq!(Value::Newtype(Box::new(Value::U32(5))).deserialize_into::<Box<u32>>().unwrap(), Box::new(5));
+    assert_eq!(Value::String("a".into()).deserialize_into::<Rc<String>>().unwrap(), Rc::new("a".to_owned()));
+    assert_eq!(
+        Value::Seq(vec![Value::U8(1), Value::U8(2)]).deserialize_into::<Arc<Vec<u8>>>().unwrap(),
+        Arc::new(vec![1, 2])
+    );
+}
+
+#[test]
+fn from_pairs() {
+    let pairs = || vec![
+        ("a".into(), Value::U8(1)),
+        ("b".into(), Value::U8(2)),
+        ("a".into(), Value::U8(3)),
+    ];
+    assert_eq!(Value::from_pairs_last_wins(pairs()), Value::map_builder().entry("a", 3u8).entry("b", 2u8).build());
+    assert_eq!(Value::from_pairs_first_wins(pairs()), Value::map_builder().entry("a", 1u8).entry("b", 2u8).build());
+}
+
+#[test]
+fn seq_element_kind() {
+    let numbers = Value::Seq(vec![Value::U8(1), Value::I64(-1), Value::F32(0.5)]);
+    assert_eq!(numbers.seq_element_kind(), Some(SeqKind::Homogeneous(ValueKind::Number)));
+    assert!(numbers.is_homogeneous_seq());
+
+    let mixed = Value::Seq(vec![Value::U8(1), Value::Unit, Value::U8(2), Value::String("a".into())]);
+    assert_eq!(mixed.seq_element_kind(), Some(SeqKind::Mixed(vec![ValueKind::Number, ValueKind::Unit, ValueKind::String])));
+    assert!(!mixed.is_homogeneous_seq());
+
+    assert_eq!(Value::Seq(vec![]).seq_element_kind(), Some(SeqKind::Empty));
+    assert_eq!(Value::Unit.seq_element_kind(), None);
+}
+
+#[test]
+fn deserialize_number_coercion() {
+    let truncate = |v: Value| ValueDeserializer::<DeserializerError>::new(v).with_number_coercion(|v, target| match (v, target) {
+        (&Value::F64(f), NumericTarget::U8) => Some(Value::U8(f as u8)),
+        _ => None,
+    });
+    assert_eq!(u8::deserialize(truncate(Value::F64(2.7))).unwrap(), 2);
+    assert_eq!(u8::deserialize(truncate(Value::Newtype(Box::new(Value::F64(1.2))))).unwrap(), 1);
+    assert!(u16::deserialize(truncate(Value::F64(2.7))).is_err());
+    assert!(u8::deserialize(Value::F64(2.7)).is_err());
+
+    let value = Value::String("5".into());
+    let parse = ValueRefDeserializer::<DeserializerError>::new(&value).with_number_coercion(|v, _| match *v {
+        Value::String(ref s) => s.parse().ok().map(Value::U64),
+        _ => None,
+    });
+    assert_eq!(i32::deserialize(parse).unwrap(), 5);
+}
+
+#[test]
+fn deserialize_number_width() {
+    use serde::de::{Deserializer, Visitor};
+
+    struct Record;
+
+    impl<'de> Visitor<'de> for Record {
+        type Value = &'static str;
+
+        fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            f.write_str("a number")
+        }
+
+        fn visit_u64<E>(self, _: u64) -> Result<&'static str, E> {
+            Ok("u64")
+        }
+
+        fn visit_i64<E>(self, _: i64) -> Result<&'static str, E> {
+            Ok("i64")
+        }
+
+        fn visit_u8<E>(self, _: u8) -> Result<&'static str, E> {
+            Ok("u8")
+        }
+
+        fn visit_i16<E>(self, _: i16) -> Result<&'static str, E> {
+            Ok("i16")
+        }
+
+        fn visit_f64<E>(self, _: f64) -> Result<&'static str, E> {
+            Ok("f64")
+        }
+    }
+
+    assert_eq!(Value::U8(5).deserialize_u64(Record).unwrap(), "u64");
+    assert_eq!((&Value::U8(5)).deserialize_i64(Record).unwrap(), "i64");
+    assert_eq!(Value::I64(5).deserialize_u8(Record).unwrap(), "u8");
+    assert_eq!(Value::U32(7).deserialize_i16(Record).unwrap(), "i16");
+    assert_eq!(Value::F32(0.5).deserialize_f64(Record).unwrap(), "f64");
+    // out of range for the requested type, so the stored width is visited
+    assert_eq!(Value::I64(-1).deserialize_u8(Record).unwrap(), "i64");
+}
+
+#[test]
+fn duration_round_trip() {
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    // serde represents both as structs, which become string-keyed maps
+    let duration = Duration::new(5, 250);
+    let value = to_value(duration).unwrap();
+    assert_eq!(value, Value::map_builder().entry("secs", 5u64).entry("nanos", 250u32).build());
+    assert_eq!(value.deserialize_into::<Duration>().unwrap(), duration);
+
+    let time = UNIX_EPOCH + Duration::new(1_000_000, 7);
+    let value = to_value(time).unwrap();
+    assert_eq!(value, Value::map_builder()
+        .entry("secs_since_epoch", 1_000_000u64)
+        .entry("nanos_since_epoch", 7u32)
+        .build());
+    assert_eq!(SystemTime::deserialize(&value).unwrap(), time);
+    assert_eq!(value.deserialize_into::<SystemTime>().unwrap(), time);
+}
+
+#[test]
+fn ordered_pairs() {
+    let value = Value::map_builder().entry("c", 3u8).entry("a", 1u8).entry(Value::U8(0), ()).build();
+    assert_eq!(value.ordered_pairs().unwrap(), [
+        (&Value::U8(0), &Value::Unit),
+        (&Value::from("a"), &Value::U8(1)),
+        (&Value::from("c"), &Value::U8(3)),
+    ]);
+    assert_eq!(Value::Unit.ordered_pairs(), None);
+}
+
+#[test]
+fn deserialize_bytes_from_string() {
+    use serde::de::{Deserializer, Visitor};
+
+    // only accepts bytes, unlike `&[u8]` which also takes a borrowed str
+    struct Bytes;
+
+    impl<'de> Visitor<'de> for Bytes {
+        type Value = &'de [u8];
+
+        fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            f.write_str("bytes")
+        }
+
+        fn visit_borrowed_bytes<E>(self, v: &'de [u8]) -> Result<&'de [u8], E> {
+            Ok(v)
+        }
+    }
+
+    let value = Value::String("abc".into());
+    let de = ValueRefDeserializer::<DeserializerError>::new(&value).lenient(true);
+    assert_eq!(de.deserialize_bytes(Bytes).unwrap(), b"abc");
+    assert_eq!(<&[u8]>::deserialize(ValueRefDeserializer::<DeserializerError>::new(&value).lenient(true)).unwrap(), b"abc");
+    assert!((&value).deserialize_bytes(Bytes).is_err());
+}
+
+#[test]
+fn into_containers() {
+    let map = Value::map_builder().entry("a", 1u8).build();
+    let mut expected = ValueMap::new();
+    expected.insert("a".into(), 1u8.into());
+    assert_eq!(map.clone().into_map(), Ok(expected));
+    assert_eq!(map.clone().into_seq(), Err(map));
+
+    let seq = Value::seq_builder().push(1u8).build();
+    assert_eq!(seq.clone().into_seq(), Ok(vec![Value::U8(1)]));
+    assert_eq!(seq.clone().into_map(), Err(seq));
+}
+
+#[test]
+fn deserialize_alias() {
+    #[derive(Deserialize, Debug, PartialEq)]
+    #[serde(deny_unknown_fields)]
+    struct Config {
+        #[serde(alias = "hostname")]
+        host: String,
+        port: u16,
+    }
+
+    let value = Value::map_builder().entry("hostname", "localhost").entry("port", 80u16).build();
+    let expected = Config {
+        host: "localhost".into(),
+        port: 80,
+    };
+    assert_eq!(Config::deserialize(&value).unwrap(), expected);
+    assert_eq!(value.clone().deserialize_into::<Config>().unwrap(), expected);
+    assert_eq!(value.deserialize_into_collecting::<Config>().unwrap(), expected);
+}
+
+#[test]
+fn non_finite_floats() {
+    let mut value = Value::map_builder()
+        .entry("a", Value::seq_builder().push(1.0f32).push(f32::NAN))
+        .entry("b", f64::INFINITY)
+        .entry("c", Value::Option(Some(Box::new(Value::F64(f64::NEG_INFINITY)))))
+        .build();
+    assert_eq!(value.find_non_finite(), vec![
+        vec![PathSegment::Key("a".into()), PathSegment::Index(1)],
+        vec![PathSegment::Key("b".into())],
+        vec![PathSegment::Key("c".into())],
+    ]);
+
+    value.replace_non_finite(Value::Unit);
+    assert_eq!(value, Value::map_builder()
+        .entry("a", Value::seq_builder().push(1.0f32).push(()))
+        .entry("b", ())
+        .entry("c", Value::Option(Some(Box::new(Value::Unit))))
+        .build());
+    assert!(value.find_non_finite().is_empty());
+}
+
+#[test]
+fn to_unexpected() {
+    let unexp = {
+        let value = Value::String("foo".into());
+        value.to_unexpected()
+    };
+    let err: DeserializerError = serde::de::Error::invalid_type(unexp.to_unexpected(), &"a number");
+    assert_eq!(err.to_string(), "Invalid type string \"foo\". Expected a number");
+}
+
+#[test]
+fn deserialize_scalar_as_seq() {
+    let de = |v| ValueDeserializer::<DeserializerError>::new(v).scalar_as_seq(true);
+    assert_eq!(Vec::<String>::deserialize(de(Value::String("x".into()))).unwrap(), ["x"]);
+    let seq = Value::Seq(vec![Value::String("x".into()), Value::String("y".into())]);
+    assert_eq!(Vec::<String>::deserialize(de(seq.clone())).unwrap(), ["x", "y"]);
+
+    let value = Value::String("x".into());
+    let de = ValueRefDeserializer::<DeserializerError>::new(&value).scalar_as_seq(true);
+    assert_eq!(Vec::<&str>::deserialize(de).unwrap(), ["x"]);
+    assert!(Vec::<String>::deserialize(&value).is_err());
+}
+
+#[test]
+fn skip_serializing_if_round_trip() {
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Config {
+        name: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        comment: Option<String>,
+    }
+
+    let config = Config {
+        name: "foo".into(),
+        comment: None,
+    };
+    let value = to_value(&config).unwrap();
+    assert_eq!(value, Value::map_builder().entry("name", "foo").build());
+    assert_eq!(value.deserialize_into::<Config>().unwrap(), config);
+}
+
+#[test]
+fn prune_empty() {
+    let mut value = Value::map_builder()
+        .entry("a", Value::map_builder().entry("b", Value::seq_builder().push(Value::seq_builder())))
+        .entry("c", Value::seq_builder().push(Value::map_builder()).push(()))
+        .build();
+    value.prune_empty();
+    assert_eq!(value, Value::map_builder().entry("c", Value::seq_builder().push(())).build());
+}
+
+#[test]
+fn find_paths() {
+    let value = Value::map_builder()
+        .entry("a", true)
+        .entry("b", Value::seq_builder().push(false).push(Value::map_builder().entry("c", true)))
+        .build();
+    assert_eq!(value.find_paths(|v| *v == Value::Bool(true)), vec![
+        vec![PathSegment::Key("a".into())],
+        vec![PathSegment::Key("b".into()), PathSegment::Index(1), PathSegment::Key("c".into())],
+    ]);
+    assert_eq!(value.find_paths(|v| v.kind() == ValueKind::Map), vec![
+        vec![],
+        vec![PathSegment::Key("b".into()), PathSegment::Index(1)],
+    ]);
+}
+
+#[test]
+fn deserialize_parsed_map_keys() {
+    let value = Value::map_builder().entry("7", true).build();
+    let de = |v| ValueDeserializer::<DeserializerError>::new(v).parse_map_keys(true);
+    let map = HashMap::<u32, bool>::deserialize(de(value.clone())).unwrap();
+    assert_eq!(map.into_iter().collect::<Vec<_>>(), [(7, true)]);
+    assert!(HashMap::<u32, bool>::deserialize(&value).is_err());
+
+    let value = Value::map_builder().entry("x", true).build();
+    match HashMap::<u32, bool>::deserialize(de(value)) {
+        Err(DeserializerError::InvalidValue(Unexpected::Str(ref v), ref exp)) => {
+            assert_eq!(v, "x");
+            assert_eq!(exp, "a map key parsable as u32");
+        },
+        res => panic!("unexpected result {:?}", res),
+    }
+
+    // values are left alone
+    let value = Value::map_builder().entry("1", "2").build();
+    assert!(HashMap::<u32, u32>::deserialize(de(value)).is_err());
+}
+
+#[test]
+fn deserialize_unit_variant_from_map() {
+    #[derive(Deserialize, Debug, PartialEq)]
+    enum Mode {
+        Fast,
+        Slow(u8),
+    }
+
+    let value = Value::map_builder().entry("Fast", ()).build();
+    assert_eq!(Mode::deserialize(&value).unwrap(), Mode::Fast);
+    assert_eq!(value.deserialize_into::<Mode>().unwrap(), Mode::Fast);
+
+    let value = Value::map_builder().entry("Fast", 1u8).build();
+    assert!(value.deserialize_into::<Mode>().is_err());
+}
+
+#[test]
+fn deserialize_size_hint() {
+    use serde::de::{Deserializer, MapAccess, SeqAccess, Visitor};
+
+    struct Hint;
+
+    impl<'de> Visitor<'de> for Hint {
+        type Value = Option<usize>;
+
+        fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            f.write_str("a seq or map")
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Option<usize>, A::Error> {
+            let hint = seq.size_hint();
+            while seq.next_element::<serde::de::IgnoredAny>()?.is_some() { }
+            Ok(hint)
+        }
+
+        fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Option<usize>, A::Error> {
+            let hint = map.size_hint();
+            while map.next_entry::<serde::de::IgnoredAny, serde::de::IgnoredAny>()?.is_some() { }
+            Ok(hint)
+        }
+    }
+
+    let seq = Value::Seq(vec![Value::Unit; 3]);
+    assert_eq!(seq.clone().deserialize_seq(Hint).unwrap(), Some(3));
+    assert_eq!((&seq).deserialize_seq(Hint).unwrap(), Some(3));
+
+    let map = Value::map_builder().entry("a", ()).entry("b", ()).build();
+    assert_eq!(map.clone().deserialize_map(Hint).unwrap(), Some(2));
+    assert_eq!((&map).deserialize_map(Hint).unwrap(), Some(2));
+    let de = ValueDeserializer::<DeserializerError>::new(map).collect_errors(true);
+    assert_eq!(de.deserialize_struct("Hint", &["a", "b"], Hint).unwrap(), Some(2));
+}
+
+#[test]
+fn from_flat() {
+    let pairs = vec![
+        ("DB__PORT", "5432"),
+        ("DB__HOST", "localhost"),
+        ("NAME", "app"),
+        ("LOG", "info"),
+        ("LOG__LEVEL", "debug"),
+    ];
+    let value = Value::from_flat(pairs.into_iter().map(|(k, v)| (k.to_owned(), v.to_owned())), "__");
+    assert_eq!(value, Value::map_builder()
+        .entry("DB", Value::map_builder().entry("PORT", "5432").entry("HOST", "localhost"))
+        .entry("NAME", "app")
+        .entry("LOG", Value::map_builder().entry("LEVEL", "debug"))
+        .build());
+}
+
+#[test]
+fn deserialize_byte_array() {
+    let bytes = Value::Bytes(vec![1, 2, 3, 4]);
+    assert_eq!(<[u8; 4]>::deserialize(&bytes).unwrap(), [1, 2, 3, 4]);
+    assert_eq!(bytes.clone().deserialize_into::<[u8; 4]>().unwrap(), [1, 2, 3, 4]);
+    let seq = Value::Seq(vec![Value::U8(1), Value::U8(2)]);
+    assert_eq!(seq.deserialize_into::<[u8; 2]>().unwrap(), [1, 2]);
+
+    match bytes.clone().deserialize_into::<[u8; 5]>() {
+        Err(DeserializerError::InvalidLength(4, ref exp)) => assert_eq!(exp, "an array of length 5"),
+        res => panic!("unexpected result {:?}", res),
+    }
+    match <[u8; 3]>::deserialize(&bytes) {
+        Err(DeserializerError::InvalidLength(4, ref exp)) => assert_eq!(exp, "3 elements in sequence"),
+        res => panic!("unexpected result {:?}", res),
+    }
+
+    let de = ValueRefDeserializer::<DeserializerError>::new(&bytes).ignore_extra_tuple_elements(true);
+    assert_eq!(<[u8; 3]>::deserialize(de).unwrap(), [1, 2, 3]);
+    let de = ValueDeserializer::<DeserializerError>::new(bytes.clone()).ignore_extra_tuple_elements(true);
+    assert_eq!(<[u8; 3]>::deserialize(de).unwrap(), [1, 2, 3]);
+
+    // the bytes count against max_ops like the elements of a seq
+    let de = ValueRefDeserializer::<DeserializerError>::new(&bytes).max_ops(3);
+    assert!(<[u8; 4]>::deserialize(de).is_err());
+    let de = ValueDeserializer::<DeserializerError>::new(bytes).max_ops(4);
+    assert_eq!(<[u8; 4]>::deserialize(de).unwrap(), [1, 2, 3, 4]);
+}
+
+#[cfg(feature = "query")]
+#[test]
+fn query_string_round_trip() {
+    let value = Value::map_builder()
+        .entry("db", Value::map_builder().entry("port", 5432u16).entry("tls", true))
+        .entry("name", "a b&c")
+        .entry("tags", Value::seq_builder().push("x").push("y"))
+        .build();
+    let query = value.to_query_string().unwrap();
+    assert_eq!(query, "db%5Bport%5D=5432&db%5Btls%5D=true&name=a+b%26c&tags%5B0%5D=x&tags%5B1%5D=y");
+
+    let expected = Value::map_builder()
+        .entry("db", Value::map_builder().entry("port", "5432").entry("tls", "true"))
+        .entry("name", "a b&c")
+        .entry("tags", Value::seq_builder().push("x").push("y"))
+        .build();
+    assert_eq!(Value::from_query_string(&query), expected);
+
+    assert!(Value::map_builder().entry(1u8, ()).build().to_query_string().is_err());
+}
+
+#[test]
+fn data_eq() {
+    let bytes = Value::Bytes(b"hi".to_vec());
+    let seq = Value::Seq(vec![Value::U8(104), Value::U64(105)]);
+    assert!(bytes.data_eq(&seq));
+    assert!(seq.data_eq(&bytes));
+    assert!(bytes != seq);
+    assert!(!bytes.data_eq(&Value::Seq(vec![Value::U8(104)])));
+
+    assert!(!bytes.data_eq(&Value::Seq(vec![Value::F64(104.0), Value::F64(105.0)])));
+    assert!(!Value::Bytes(vec![1]).data_eq(&Value::Seq(vec![Value::I16(257)])));
+
+    assert!(Value::U64(u64::MAX).data_eq(&Value::U64(u64::MAX)));
+    assert!(!Value::U64(u64::MAX).data_eq(&Value::U64(u64::MAX - 1)));
+    assert!(Value::I8(-1).data_eq(&Value::I64(-1)));
+    assert!(!Value::I64(i64::MIN).data_eq(&Value::U64(1 << 63)));
+
+    let map = |v| Value::map_builder().entry("data", v).entry("n", 1u8).build();
+    assert!(map(bytes).data_eq(&Value::map_builder().entry("data", seq).entry("n", 1.0f64).build()));
+}
+
+#[test]
+fn deserialize_into_validated() {
+    let validate = |v: &Value| match v.try_get::<u16>("port") {
+        Ok(0) => Err("port must not be 0".to_owned()),
+        _ => Ok(()),
+    };
+    let value = Value::map_builder().entry("port", 80u16).build();
+    assert_eq!(value.deserialize_into_validated::<HashMap<String, u16>, _>(validate).unwrap()["port"], 80);
+
+    let value = Value::map_builder().entry("port", 0u16).build();
+    match value.deserialize_into_validated::<HashMap<String, u16>, _>(validate) {
+        Err(DeserializerError::Custom(ref msg)) => assert_eq!(msg, "port must not be 0"),
+        res => panic!("unexpected result {:?}", res),
+    }
+}
+
+#[test]
+fn deserialize_newtype_option() {
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Wrapper(Option<u32>);
+
+    for wrapper in &[Wrapper(Some(5)), Wrapper(None)] {
+        let value = to_value(wrapper).unwrap();
+        assert_eq!(Wrapper::deserialize(&value).unwrap(), *wrapper);
+        assert_eq!(value.clone().deserialize_into::<Wrapper>().unwrap(), *wrapper);
+        assert_eq!(value.deserialize_into::<Option<u32>>().unwrap(), wrapper.0);
+    }
+
+    let value = Value::Newtype(Box::new(Value::U32(5)));
+    assert_eq!(value.deserialize_into::<Option<u32>>().unwrap(), Some(5));
+}
+
+#[test]
+fn to_value_bounded_nodes() {
+    let big = vec![0u32; 1000];
+    match to_value_bounded(&big, 100) {
+        Err(SerializerError::TooManyNodes(100)) => (),
+        res => panic!("unexpected result {:?}", res),
+    }
+
+    #[derive(Serialize)]
+    enum Variant {
+        Struct { a: Vec<u8>, b: Option<bool> },
+    }
+
+    let variant = Variant::Struct { a: vec![1, 2], b: Some(true) };
+    let value = to_value(&variant).unwrap();
+    let count = value.node_count();
+    assert_eq!(to_value_bounded(&variant, count).unwrap(), value);
+    assert!(to_value_bounded(&variant, count - 1).is_err());
+}
+
+#[test]
+fn raw_value_defers_subtree() {
+    #[derive(Deserialize, Serialize)]
+    struct Envelope {
+        kind: String,
+        payload: RawValue,
+    }
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Payload {
+        id: u32,
+        tags: Vec<String>,
+    }
+
+    let payload = Value::map_builder()
+        .entry("id", 7u8)
+        .entry("tags", Value::Seq(vec![Value::String("a".into()), Value::String("b".into())]))
+        .build();
+    let value = Value::map_builder()
+        .entry("kind", "payload")
+        .entry("payload", payload.clone())
+        .build();
+
+    let envelope: Envelope = value.deserialize_into().unwrap();
+    assert_eq!(envelope.kind, "payload");
+    assert_eq!(envelope.payload.0, payload);
+    assert_eq!(to_value(&envelope).unwrap().pointer("/payload/tags/1"), Some(&Value::String("b".into())));
+
+    let payload: Payload = envelope.payload.0.deserialize_into().unwrap();
+    assert_eq!(payload, Payload { id: 7, tags: vec!["a".into(), "b".into()] });
+}
+
+#[test]
+fn deserialize_lenient_char_from_bytes() {
+    let value = Value::Bytes("é".as_bytes().to_vec());
+    assert_eq!(char::deserialize(ValueRefDeserializer::<DeserializerError>::new(&value).lenient(true)).unwrap(), 'é');
+    assert_eq!(char::deserialize(ValueDeserializer::<DeserializerError>::new(value.clone()).lenient(true)).unwrap(), 'é');
+    assert!(char::deserialize(&value).is_err());
+
+    for bytes in &[&b"ab"[..], &b""[..], &[0xff][..]] {
+        let value = Value::Bytes(bytes.to_vec());
+        assert!(char::deserialize(ValueRefDeserializer::<DeserializerError>::new(&value).lenient(true)).is_err());
+    }
+}
+
+#[test]
+fn join_strings() {
+    let value = Value::Seq(vec![Value::String("a".into()), Value::String("b".into()), Value::String("c".into())]);
+    assert_eq!(value.join_strings(", ").as_deref(), Some("a, b, c"));
+    assert_eq!(Value::Seq(vec![]).join_strings(","), Some(String::new()));
+
+    let value = Value::Seq(vec![Value::String("a".into()), Value::U8(1)]);
+    assert_eq!(value.join_strings(","), None);
+    assert_eq!(Value::String("a".into()).join_strings(","), None);
+}
+
+#[test]
+fn deserialize_ignore_extra_tuple_elements() {
+    let value = Value::Seq(vec![Value::U8(1), Value::String("a".into()), Value::Bool(true)]);
+    assert!(<(u8, String)>::deserialize(&value).is_err());
+
+    let de = ValueRefDeserializer::<DeserializerError>::new(&value).ignore_extra_tuple_elements(true);
+    assert_eq!(<(u8, String)>::deserialize(de).unwrap(), (1, "a".into()));
+    let de = ValueDeserializer::<DeserializerError>::new(value).ignore_extra_tuple_elements(true);
+    assert_eq!(<(u8, String)>::deserialize(de).unwrap(), (1, "a".into()));
+}
+
+#[test]
+fn deserialize_map_as_pairs() {
+    let value = Value::map_builder()
+        .entry("z", 1u8)
+        .entry("a", 2u8)
+        .entry("m", 3u8)
+        .build();
+    // the map's own order: insertion order with preserve_order, else sorted
+    #[cfg(feature = "preserve_order")]
+    let expected = vec![("z".to_owned(), 1), ("a".to_owned(), 2), ("m".to_owned(), 3)];
+    #[cfg(not(feature = "preserve_order"))]
+    let expected = vec![("a".to_owned(), 2), ("m".to_owned(), 3), ("z".to_owned(), 1)];
+
+    let de = ValueRefDeserializer::<DeserializerError>::new(&value).map_as_pairs(true);
+    assert_eq!(Vec::<(String, u8)>::deserialize(de).unwrap(), expected);
+    let de = ValueDeserializer::<DeserializerError>::new(value.clone()).map_as_pairs(true);
+    assert_eq!(Vec::<(String, u8)>::deserialize(de).unwrap(), expected);
+    assert!(Vec::<(String, u8)>::deserialize(&value).is_err());
+    let de = ValueRefDeserializer::<DeserializerError>::new(&value).lenient(true);
+    assert!(Vec::<(String, u8)>::deserialize(de).is_err());
+}
+
+#[test]
+fn apply_merge_patch() {
+    let mut value = Value::map_builder()
+        .entry("a", "b")
+        .entry("c", Value::map_builder().entry("d", "e").entry("f", "g").build())
+        .entry("list", Value::Seq(vec![Value::U8(1), Value::U8(2)]))
+        .build();
+    let patch = Value::map_builder()
+        .entry("a", "z")
+        .entry("c", Value::map_builder().entry("f", Value::Unit).entry("h", 1u8).build())
+        .entry("list", Value::Seq(vec![Value::U8(3)]))
+        .entry("missing", Value::Option(None))
+        .build();
+    value.apply_merge_patch(patch);
+
+    let expected = Value::map_builder()
+        .entry("a", "z")
+        .entry("c", Value::map_builder().entry("d", "e").entry("h", 1u8).build())
+        .entry("list", Value::Seq(vec![Value::U8(3)]))
+        .build();
+    assert_eq!(value, expected);
+
+    let mut value = Value::Seq(vec![Value::U8(1)]);
+    value.apply_merge_patch(Value::map_builder().entry("a", Value::Unit).entry("b", 2u8).build());
+    assert_eq!(value, Value::map_builder().entry("b", 2u8).build());
+
+    value.apply_merge_patch(Value::String("x".into()));
+    assert_eq!(value, Value::String("x".into()));
+}
+
+#[test]
+fn apply_json_patch() {
+    let seq = |v: &[u8]| Value::Seq(v.iter().cloned().map(Value::U8).collect());
+    let mut value = Value::map_builder()
+        .entry("a", seq(&[1, 2, 3]))
+        .entry("b", Value::map_builder().entry("c", "d").build())
+        .build();
+
+    value.apply_json_patch(&[
+        PatchOp::Add { path: "/a/1".into(), value: Value::U8(9) },
+        PatchOp::Add { path: "/a/-".into(), value: Value::U8(4) },
+        PatchOp::Add { path: "/b/e".into(), value: Value::Bool(true) },
+        PatchOp::Remove { path: "/a/0".into() },
+        PatchOp::Replace { path: "/b/c".into(), value: Value::String("x".into()) },
+        PatchOp::Copy { from: "/b/c".into(), path: "/f".into() },
+        PatchOp::Move { from: "/b/e".into(), path: "/g".into() },
+        PatchOp::Test { path: "/a".into(), value: seq(&[9, 2, 3, 4]) },
+    ]).unwrap();
+    let expected = Value::map_builder()
+        .entry("a", seq(&[9, 2, 3, 4]))
+        .entry("b", Value::map_builder().entry("c", "x").build())
+        .entry("f", "x")
+        .entry("g", true)
+        .build();
+    assert_eq!(value, expected);
+
+    // failed operations leave the value untouched
+    let ops = [
+        PatchOp::Remove { path: "/a".into() },
+        PatchOp::Test { path: "/f".into(), value: Value::String("y".into()) },
+    ];
+    assert_eq!(value.apply_json_patch(&ops), Err(PatchError::TestFailed("/f".into())));
+    assert_eq!(value, expected);
+
+    let ops = [PatchOp::Replace { path: "/missing".into(), value: Value::Unit }];
+    assert_eq!(value.apply_json_patch(&ops), Err(PatchError::Path(PathError::Missing("missing".into()))));
+    let ops = [PatchOp::Add { path: "/a/9".into(), value: Value::Unit }];
+    assert_eq!(value.apply_json_patch(&ops), Err(PatchError::Path(PathError::InvalidIndex("9".into()))));
+    let ops = [PatchOp::Move { from: "/b".into(), path: "/b/c".into() }];
+    assert_eq!(value.apply_json_patch(&ops), Err(PatchError::MoveIntoChild("/b".into())));
+    assert_eq!(value, expected);
+}
+
+#[test]
+fn deserialize_strict_float_narrowing() {
+    let exact = Value::F64(0.5);
+    let inexact = Value::F64(0.1);
+    assert_eq!(f32::deserialize(&inexact).unwrap(), 0.1);
+
+    let strict = |v| ValueRefDeserializer::<DeserializerError>::new(v).strict_float_narrowing(true);
+    assert_eq!(f32::deserialize(strict(&exact)).unwrap(), 0.5);
+    assert!(f32::deserialize(strict(&inexact)).is_err());
+    assert_eq!(f64::deserialize(strict(&inexact)).unwrap(), 0.1);
+
+    let de = ValueDeserializer::<DeserializerError>::new(inexact).strict_float_narrowing(true);
+    assert!(f32::deserialize(de).is_err());
+}
+
+#[test]
+fn eq_ignoring_keys() {
+    let event = |timestamp: u64| Value::map_builder()
+        .entry("id", 1u8)
+        .entry("meta", Value::map_builder().entry("source", "a").entry("timestamp", timestamp).build())
+        .entry("timestamp", timestamp)
+        .build();
+    assert!(event(100).eq_ignoring_keys(&event(200), &["timestamp"]));
+    assert!(!event(100).eq_ignoring_keys(&event(200), &["id"]));
+
+    let mut other = event(200);
+    other.set("/meta/extra", Value::Bool(true), false).unwrap();
+    assert!(!event(100).eq_ignoring_keys(&other, &["timestamp"]));
+    assert!(event(100).eq_ignoring_keys(&other, &["timestamp", "extra"]));
+}
+
+#[test]
+fn deserialize_number_as_string() {
+    #[derive(Debug, PartialEq)]
+    struct Parsed(u64);
+
+    impl<'de> Deserialize<'de> for Parsed {
+        fn deserialize<D: serde::Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+            struct ParsedVisitor;
+
+            impl<'de> serde::de::Visitor<'de> for ParsedVisitor {
+                type Value = Parsed;
+
+                fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                    f.write_str("a numeric string")
+                }
+
+                fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<Parsed, E> {
+                    v.parse().map(Parsed).map_err(E::custom)
+                }
+            }
+
+            d.deserialize_str(ParsedVisitor)
+        }
+    }
+
+    let value = Value::U32(42);
+    assert!(Parsed::deserialize(&value).is_err());
+    let de = ValueRefDeserializer::<DeserializerError>::new(&value).number_as_string(true);
+    assert_eq!(Parsed::deserialize(de).unwrap(), Parsed(42));
+    let de = ValueDeserializer::<DeserializerError>::new(value).number_as_string(true);
+    assert_eq!(Parsed::deserialize(de).unwrap(), Parsed(42));
+
+    for (value, expected) in [(Value::Bool(true), "true"), (Value::Char('x'), "x"), (Value::F64(1.5), "1.5")] {
+        let de = ValueDeserializer::<DeserializerError>::new(value).number_as_string(true);
+        assert_eq!(String::deserialize(de).unwrap(), expected);
+    }
+}
+
+#[test]
+fn bigint_round_trip() {
+    let mut bytes = vec![0x00];
+    bytes.extend((1..=20).map(|b| b * 12));
+    let value = Value::Seq(vec![Value::BigInt(bytes), Value::U8(1)]);
+
+    assert_eq!(to_value(&value).unwrap(), value);
+    assert_eq!(Value::deserialize(&value).unwrap(), value);
+    assert_eq!(value.clone().deserialize_into::<Value>().unwrap(), value);
+    assert_eq!(value.kind(), ValueKind::Seq);
+    assert!(Value::BigInt(vec![]).is_number());
+
+    // the deserializer options reach the bytes inside the private struct
+    let de = ValueDeserializer::<DeserializerError>::new(value.clone()).max_depth(1);
+    assert!(Value::deserialize(de).is_err());
+    let de = ValueDeserializer::<DeserializerError>::new(value.clone()).max_ops(2);
+    assert!(Value::deserialize(de).is_err());
+    let de = ValueRefDeserializer::<DeserializerError>::new(&value).max_ops(2);
+    assert!(Value::deserialize(de).is_err());
+    let de = ValueDeserializer::<DeserializerError>::new(value).max_ops(3);
+    assert!(Value::deserialize(de).is_ok());
+}
+
+#[test]
+fn bigint_compares_by_value() {
+    use std::collections::hash_map::DefaultHasher;
+
+    let hash = |v: &Value| {
+        let mut hasher = DefaultHasher::new();
+        v.hash(&mut hasher);
+        hasher.finish()
+    };
+    let big = |v: &[u8]| Value::BigInt(v.to_vec());
+
+    for (v0, v1) in [(big(&[0x00, 0x01]), big(&[0x01])), (big(&[0xff, 0xff]), big(&[0xff])), (big(&[0x00]), big(&[]))] {
+        assert_eq!(v0, v1);
+        assert_eq!(hash(&v0), hash(&v1));
+    }
+    assert_ne!(big(&[0x00, 0x80]), big(&[0x80]));
+
+    // -0x100, -0x80, -1, 0, 1, 0x7f, 0x80, 0x100
+    let ascending = [
+        big(&[0xff, 0x00]), big(&[0x80]), big(&[0xff]), big(&[]),
+        big(&[0x01]), big(&[0x7f]), big(&[0x00, 0x80]), big(&[0x01, 0x00]),
+    ];
+    for (i, v0) in ascending.iter().enumerate() {
+        for (j, v1) in ascending.iter().enumerate() {
+            assert_eq!(v0.cmp(v1), i.cmp(&j), "{:?} vs {:?}", v0, v1);
+        }
+    }
+}
+
+#[cfg(feature = "cbor")]
+#[test]
+fn cbor_bignum_round_trip() {
+    use std::convert::TryFrom;
+    use ciborium::value::Value as CborValue;
+
+    let magnitude: Vec<u8> = (0xec..=0xff).collect();
+    assert_eq!(magnitude.len(), 20);
+
+    let positive = CborValue::Tag(2, Box::new(CborValue::Bytes(magnitude.clone())));
+    let value = Value::try_from(positive.clone()).unwrap();
+    let mut expected = vec![0x00];
+    expected.extend(&magnitude);
+    assert_eq!(value, Value::BigInt(expected));
+    assert_eq!(CborValue::from(value), positive);
+
+    // -1 - 0xff is -0x100
+    let negative = CborValue::Tag(3, Box::new(CborValue::Bytes(vec![0xff])));
+    let value = Value::try_from(negative.clone()).unwrap();
+    assert_eq!(value, Value::BigInt(vec![0xff, 0x00]));
+    assert_eq!(CborValue::from(value), negative);
+
+    // an empty magnitude is -1 for tag 3 and zero for tag 2
+    let minus_one = CborValue::Tag(3, Box::new(CborValue::Bytes(vec![])));
+    let value = Value::try_from(minus_one.clone()).unwrap();
+    assert_eq!(value, Value::BigInt(vec![0xff]));
+    assert_eq!(CborValue::from(value), minus_one);
+    let zero = CborValue::Tag(2, Box::new(CborValue::Bytes(vec![])));
+    assert_eq!(Value::try_from(zero).unwrap(), Value::BigInt(vec![]));
+}
+
+#[test]
+fn deserialize_lenient_unit_from_empty() {
+    let empty = [Value::Seq(vec![]), Value::Map(ValueMap::new())];
+    for value in &empty {
+        assert!(<()>::deserialize(value).is_err());
+        <()>::deserialize(ValueRefDeserializer::<DeserializerError>::new(value).lenient(true)).unwrap();
+        <()>::deserialize(ValueDeserializer::<DeserializerError>::new(value.clone()).lenient(true)).unwrap();
+    }
+
+    let full = [Value::Seq(vec![Value::Unit]), Value::map_builder().entry("a", Value::Unit).build()];
+    for value in &full {
+        assert!(<()>::deserialize(ValueRefDeserializer::<DeserializerError>::new(value).lenient(true)).is_err());
+        assert!(<()>::deserialize(ValueDeserializer::<DeserializerError>::new(value.clone()).lenient(true)).is_err());
+    }
+}
+
+#[test]
+fn deserialize_any_preserves_sign() {
+    #[derive(Debug, PartialEq)]
+    enum Sign {
+        Signed(i64),
+        Unsigned(u64),
+    }
+
+    impl<'de> Deserialize<'de> for Sign {
+        fn deserialize<D: serde::Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+            struct SignVisitor;
+
+            impl<'de> serde::de::Visitor<'de> for SignVisitor {
+                type Value = Sign;
+
+                fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                    f.write_str("an integer")
+                }
+
+                fn visit_i64<E>(self, v: i64) -> Result<Sign, E> {
+                    Ok(Sign::Signed(v))
+                }
+
+                fn visit_u64<E>(self, v: u64) -> Result<Sign, E> {
+                    Ok(Sign::Unsigned(v))
+                }
+            }
+
+            d.deserialize_any(SignVisitor)
+        }
+    }
+
+    let cases = [
+        (Value::I8(-5), Sign::Signed(-5)),
+        (Value::I16(5), Sign::Signed(5)),
+        (Value::I32(-5), Sign::Signed(-5)),
+        (Value::I64(-5), Sign::Signed(-5)),
+        (Value::U8(5), Sign::Unsigned(5)),
+        (Value::U16(5), Sign::Unsigned(5)),
+        (Value::U32(5), Sign::Unsigned(5)),
+        (Value::U64(u64::MAX), Sign::Unsigned(u64::MAX)),
+    ];
+    for (value, expected) in &cases {
+        assert_eq!(Sign::deserialize(value).unwrap(), *expected);
+        assert_eq!(value.clone().deserialize_into::<Sign>().unwrap(), *expected);
+    }
+}
+
+#[test]
+fn deserialize_sort_map_entries() {
+    let value = Value::map_builder()
+        .entry("z", 1u8)
+        .entry("a", 2u8)
+        .entry("m", 3u8)
+        .build();
+    let expected = vec![("a".to_owned(), 2), ("m".to_owned(), 3), ("z".to_owned(), 1)];
+
+    let de = ValueRefDeserializer::<DeserializerError>::new(&value).map_as_pairs(true).sort_map_entries(true);
+    assert_eq!(Vec::<(String, u8)>::deserialize(de).unwrap(), expected);
+    let de = ValueDeserializer::<DeserializerError>::new(value).map_as_pairs(true).sort_map_entries(true);
+    assert_eq!(Vec::<(String, u8)>::deserialize(de).unwrap(), expected);
+}
+
+#[test]
+fn result_round_trip() {
+    for result in &[Ok(5u32), Err("failed".to_owned())] {
+        let value = to_value(result).unwrap();
+        let key = if result.is_ok() { "Ok" } else { "Err" };
+        assert_eq!(value.ordered_pairs().unwrap()[0].0, &Value::String(key.into()));
+        assert_eq!(Result::<u32, String>::deserialize(&value).unwrap(), *result);
+        assert_eq!(value.deserialize_into::<Result<u32, String>>().unwrap(), *result);
+    }
+}
+
+#[test]
+fn simplify() {
+    let newtype = |v| Value::Newtype(Box::new(v));
+    let some = |v| Value::Option(Some(Box::new(v)));
+
+    let mut value = Value::Seq(vec![
+        newtype(newtype(Value::U8(1))),
+        some(newtype(some(Value::U8(2)))),
+        some(Value::Option(None)),
+        Value::map_builder().entry(newtype(Value::String("k".into())), newtype(Value::Unit)).build(),
+    ]);
+    value.simplify();
+    assert_eq!(value, Value::Seq(vec![
+        Value::U8(1),
+        some(Value::U8(2)),
+        some(Value::Option(None)),
+        Value::map_builder().entry("k", Value::Unit).build(),
+    ]));
+}
+
+#[test]
+fn deserialize_borrowed_bytes_field() {
+    #[derive(Deserialize)]
+    struct Packet<'a> {
+        id: u8,
+        #[serde(borrow)]
+        payload: &'a [u8],
+    }
+
+    let value = Value::map_builder()
+        .entry("id", 1u8)
+        .entry("payload", Value::Bytes(b"data".to_vec()))
+        .build();
+    let packet = Packet::deserialize(&value).unwrap();
+    assert_eq!(packet.id, 1);
+    assert_eq!(packet.payload, b"data");
+    match value.pointer("/payload") {
+        Some(Value::Bytes(v)) => assert_eq!(packet.payload.as_ptr(), v.as_ptr()),
+        v => panic!("unexpected value {:?}", v),
+    }
+}
+
+#[test]
+fn deserialize_case_insensitive_variants() {
+    #[derive(Deserialize, Debug, PartialEq)]
+    enum Mode {
+        Foo,
+        Bar,
+        Baz(u8),
+    }
+
+    let value = Value::String("bar".into());
+    assert!(Mode::deserialize(&value).is_err());
+    let de = ValueRefDeserializer::<DeserializerError>::new(&value).case_insensitive_variants(true);
+    assert_eq!(Mode::deserialize(de).unwrap(), Mode::Bar);
+    let de = ValueDeserializer::<DeserializerError>::new(value).case_insensitive_variants(true);
+    assert_eq!(Mode::deserialize(de).unwrap(), Mode::Bar);
+
+    let value = Value::map_builder().entry("BAZ", 1u8).build();
+    let de = ValueRefDeserializer::<DeserializerError>::new(&value).case_insensitive_variants(true);
+    assert_eq!(Mode::deserialize(de).unwrap(), Mode::Baz(1));
+
+    let value = Value::String("qux".into());
+    let de = ValueRefDeserializer::<DeserializerError>::new(&value).case_insensitive_variants(true);
+    assert!(Mode::deserialize(de).is_err());
+}
+
+#[test]
+fn deserialize_numeric_identifiers() {
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Point {
+        x: i32,
+        y: i32,
+    }
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    enum Shape {
+        Dot,
+        Line(u8),
+    }
+
+    let value = Value::Map(vec![
+        (Value::U8(0), Value::I32(3)),
+        (Value::U64(1), Value::I32(-4)),
+    ].into_iter().collect());
+    assert_eq!(Point::deserialize(&value).unwrap(), Point { x: 3, y: -4 });
+    assert_eq!(value.deserialize_into::<Point>().unwrap(), Point { x: 3, y: -4 });
+
+    let value = Value::Map(vec![(Value::U32(1), Value::U8(2))].into_iter().collect());
+    assert_eq!(Shape::deserialize(&value).unwrap(), Shape::Line(2));
+    assert_eq!(value.deserialize_into::<Shape>().unwrap(), Shape::Line(2));
+    assert!(Shape::deserialize(&Value::Map(vec![(Value::U8(2), Value::Unit)].into_iter().collect())).is_err());
+    assert_eq!(Shape::deserialize(&Value::String("Dot".into())).unwrap(), Shape::Dot);
+}
+
+#[test]
+fn net_addr_round_trip() {
+    use std::net::{IpAddr, SocketAddr};
+
+    let ips: Vec<IpAddr> = vec!["127.0.0.1".parse().unwrap(), "::1".parse().unwrap()];
+    let sockets: Vec<SocketAddr> = vec!["10.0.0.1:80".parse().unwrap(), "[::1]:8080".parse().unwrap()];
+
+    let value = to_value(&ips).unwrap();
+    assert_eq!(value.pointer("/0"), Some(&Value::String("127.0.0.1".into())));
+    assert_eq!(Vec::<IpAddr>::deserialize(&value).unwrap(), ips);
+    assert_eq!(value.deserialize_into::<Vec<IpAddr>>().unwrap(), ips);
+    let value = to_value(&sockets).unwrap();
+    assert_eq!(value.pointer("/1"), Some(&Value::String("[::1]:8080".into())));
+    assert_eq!(Vec::<SocketAddr>::deserialize(&value).unwrap(), sockets);
+
+    let value = to_value(HumanReadable::new(&ips, false)).unwrap();
+    assert_eq!(value.pointer("/0/V4"), Some(&Value::Seq(vec![Value::U8(127), Value::U8(0), Value::U8(0), Value::U8(1)])));
+    assert!(Vec::<IpAddr>::deserialize(&value).is_err());
+    let de = ValueRefDeserializer::<DeserializerError>::new(&value).human_readable(false);
+    assert_eq!(Vec::<IpAddr>::deserialize(de).unwrap(), ips);
+    let de = ValueDeserializer::<DeserializerError>::new(value).human_readable(false);
+    assert_eq!(Vec::<IpAddr>::deserialize(de).unwrap(), ips);
+
+    let value = to_value(HumanReadable::new(&sockets, false)).unwrap();
+    assert_eq!(value.pointer("/0/V4/1"), Some(&Value::U16(80)));
+    let de = ValueRefDeserializer::<DeserializerError>::new(&value).human_readable(false);
+    assert_eq!(Vec::<SocketAddr>::deserialize(de).unwrap(), sockets);
+}
+
+#[test]
+fn deserialize_bytes_moves_and_sizes_buffers() {
+    struct Buf {
+        bytes: Vec<u8>,
+        size_hint: Option<usize>,
+    }
+
+    impl<'de> Deserialize<'de> for Buf {
+        fn deserialize<D: serde::Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+            struct BufVisitor;
+
+            impl<'de> serde::de::Visitor<'de> for BufVisitor {
+                type Value = Buf;
+
+                fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                    f.write_str("bytes")
+                }
+
+                fn visit_byte_buf<E>(self, bytes: Vec<u8>) -> Result<Buf, E> {
+                    Ok(Buf { bytes, size_hint: None })
+                }
+
+                fn visit_seq<A: serde::de::SeqAccess<'de>>(self, mut seq: A) -> Result<Buf, A::Error> {
+                    let size_hint = seq.size_hint();
+                    let mut bytes = Vec::with_capacity(size_hint.unwrap_or(0));
+                    while let Some(b) = seq.next_element()? {
+                        bytes.push(b);
+                    }
+                    Ok(Buf { bytes, size_hint })
+                }
+            }
+
+            d.deserialize_byte_buf(BufVisitor)
+        }
+    }
+
+    let bytes = vec![1u8; 4096];
+    let ptr = bytes.as_ptr();
+    let buf = Value::Bytes(bytes).deserialize_into::<Buf>().unwrap();
+    assert_eq!(buf.bytes.as_ptr(), ptr);
+
+    let value = Value::Seq(vec![Value::U8(1); 100]);
+    let buf = value.deserialize_into::<Buf>().unwrap();
+    assert_eq!(buf.size_hint, Some(100));
+    assert_eq!(buf.bytes, vec![1; 100]);
+}
+
+#[test]
+fn deserialize_owned_strings_without_copying() {
+    let string = "x".repeat(1 << 16);
+    let ptr = string.as_ptr();
+    let string = Value::String(string).deserialize_into::<String>().unwrap();
+    assert_eq!(string.as_ptr(), ptr);
+
+    #[derive(Deserialize)]
+    struct Doc {
+        body: String,
+        tags: Vec<String>,
+    }
+
+    let body = "y".repeat(1 << 16);
+    let tag = "z".repeat(1 << 10);
+    let (body_ptr, tag_ptr) = (body.as_ptr(), tag.as_ptr());
+    let value = Value::map_builder()
+        .entry("body", body)
+        .entry("tags", Value::Seq(vec![Value::String(tag)]))
+        .build();
+    let doc = value.deserialize_into::<Doc>().unwrap();
+    assert_eq!(doc.body.as_ptr(), body_ptr);
+    assert_eq!(doc.tags[0].as_ptr(), tag_ptr);
+}
+
+#[test]
+fn serialize_newtype_as() {
+    use serde::ser::{Impossible, Serialize, Serializer};
+    use serde::de::value::Error;
+
+    // records the name of a newtype struct, rejecting anything else
+    struct NameSerializer;
+
+    macro_rules! unsupported {
+        ($($func:ident($($arg:ty),*) -> $ret:ty;)*) => {
+            $(
+                fn $func(self, $(_: $arg),*) -> Result<$ret, Error> {
+                    Err(serde::ser::Error::custom("unsupported"))
+                }
+            )*
+        };
+    }
+
+    impl Serializer for NameSerializer {
+        type Ok = String;
+        type Error = Error;
+        type SerializeSeq = Impossible<String, Error>;
+        type SerializeTuple = Impossible<String, Error>;
+        type SerializeTupleStruct = Impossible<String, Error>;
+        type SerializeTupleVariant = Impossible<String, Error>;
+        type SerializeMap = Impossible<String, Error>;
+        type SerializeStruct = Impossible<String, Error>;
+        type SerializeStructVariant = Impossible<String, Error>;
+
+        fn serialize_newtype_struct<T: ?Sized + Serialize>(self, name: &'static str, _: &T) -> Result<String, Error> {
+            Ok(name.to_owned())
+        }
+
+        fn serialize_some<T: ?Sized + Serialize>(self, _: &T) -> Result<String, Error> {
+            Err(serde::ser::Error::custom("unsupported"))
+        }
+
+        fn serialize_newtype_variant<T: ?Sized + Serialize>(self, _: &'static str, _: u32, _: &'static str, _: &T) -> Result<String, Error> {
+            Err(serde::ser::Error::custom("unsupported"))
+        }
+
+        unsupported! {
+            serialize_bool(bool) -> String;
+            serialize_i8(i8) -> String;
+            serialize_i16(i16) -> String;
+            serialize_i32(i32) -> String;
+            serialize_i64(i64) -> String;
+            serialize_u8(u8) -> String;
+            serialize_u16(u16) -> String;
+            serialize_u32(u32) -> String;
+            serialize_u64(u64) -> String;
+            serialize_f32(f32) -> String;
+            serialize_f64(f64) -> String;
+            serialize_char(char) -> String;
+            serialize_str(&str) -> String;
+            serialize_bytes(&[u8]) -> String;
+            serialize_none() -> String;
+            serialize_unit() -> String;
+            serialize_unit_struct(&'static str) -> String;
+            serialize_unit_variant(&'static str, u32, &'static str) -> String;
+            serialize_seq(Option<usize>) -> Self::SerializeSeq;
+            serialize_tuple(usize) -> Self::SerializeTuple;
+            serialize_tuple_struct(&'static str, usize) -> Self::SerializeTupleStruct;
+            serialize_tuple_variant(&'static str, u32, &'static str, usize) -> Self::SerializeTupleVariant;
+            serialize_map(Option<usize>) -> Self::SerializeMap;
+            serialize_struct(&'static str, usize) -> Self::SerializeStruct;
+            serialize_struct_variant(&'static str, u32, &'static str, usize) -> Self::SerializeStructVariant;
+        }
+    }
+
+    let value = Value::Newtype(Box::new(Value::U8(1)));
+    assert_eq!(value.serialize(NameSerializer).unwrap(), "");
+    assert_eq!(value.serialize_newtype_as("Meters").serialize(NameSerializer).unwrap(), "Meters");
+    assert_eq!(to_value(value.serialize_newtype_as("Meters")).unwrap(), value);
+    assert_eq!(to_value(Value::U8(1).serialize_newtype_as("Meters")).unwrap(), Value::U8(1));
+}
+
+#[test]
+fn is_subset_of() {
+    let config = Value::map_builder()
+        .entry("name", "app")
+        .entry("db", Value::map_builder().entry("host", "localhost").entry("port", 5432u16).build())
+        .entry("features", Value::Seq(vec![Value::String("a".into()), Value::String("b".into())]))
+        .build();
+
+    let expected = Value::map_builder()
+        .entry("db", Value::map_builder().entry("port", 5432u16).build())
+        .build();
+    assert!(expected.is_subset_of(&config));
+    assert!(config.is_subset_of(&config));
+    assert!(!config.is_subset_of(&expected));
+
+    let missing = Value::map_builder()
+        .entry("db", Value::map_builder().entry("user", "root").build())
+        .build();
+    assert!(!missing.is_subset_of(&config));
+
+    let partial_seq = Value::map_builder()
+        .entry("features", Value::Seq(vec![Value::String("a".into())]))
+        .build();
+    assert!(!partial_seq.is_subset_of(&config));
+}
+
+#[test]
+fn from_pairs_checked() {
+    let pairs = vec![
+        (Value::String("a".into()), Value::U8(1)),
+        (Value::String("b".into()), Value::U8(2)),
+    ];
+    assert_eq!(Value::from_pairs_checked(pairs.clone()).unwrap(), Value::from_pairs_last_wins(pairs));
+
+    let pairs = vec![
+        (Value::String("a".into()), Value::U8(1)),
+        (Value::String("b".into()), Value::U8(2)),
+        (Value::String("a".into()), Value::U8(3)),
+    ];
+    assert_eq!(Value::from_pairs_checked(pairs), Err(DuplicateKeyError(Value::String("a".into()))));
+}
+
+#[test]
+fn deserialize_degenerate_structs() {
+    use std::marker::PhantomData;
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Unit;
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Empty {}
+
+    assert_eq!(PhantomData::<u32>::deserialize(&Value::Unit).unwrap(), PhantomData);
+    assert_eq!(Value::Unit.deserialize_into::<PhantomData<u32>>().unwrap(), PhantomData);
+    assert_eq!(Unit::deserialize(&Value::Unit).unwrap(), Unit);
+    assert_eq!(Value::Unit.deserialize_into::<Unit>().unwrap(), Unit);
+
+    let empty = Value::Map(ValueMap::new());
+    assert_eq!(Empty::deserialize(&empty).unwrap(), Empty {});
+    assert_eq!(empty.clone().deserialize_into::<Empty>().unwrap(), Empty {});
+    assert_eq!(empty.deserialize_into_collecting::<Empty>().unwrap(), Empty {});
+    assert!(Unit::deserialize(&Value::U8(0)).is_err());
+}
+
+#[test]
+fn deserialize_borrowed_map_keys() {
+    #[derive(Deserialize)]
+    struct Counts<'a> {
+        #[serde(borrow)]
+        counts: HashMap<&'a str, u8>,
+        #[serde(borrow)]
+        blobs: HashMap<&'a [u8], u8>,
+    }
+
+    let value = Value::map_builder()
+        .entry("counts", Value::map_builder().entry("a", 1u8).entry("b", 2u8).build())
+        .entry("blobs", Value::Map(vec![(Value::Bytes(b"x".to_vec()), Value::U8(3))].into_iter().collect()))
+        .build();
+    let counts = Counts::deserialize(&value).unwrap();
+    assert_eq!(counts.counts["a"], 1);
+    assert_eq!(counts.counts["b"], 2);
+    assert_eq!(counts.blobs[&b"x"[..]], 3);
+}
+
+#[test]
+fn value_seed_reject_non_finite() {
+    use serde::de::DeserializeSeed;
+
+    let input = Value::Seq(vec![Value::F64(1.5), Value::F32(f32::NAN)]);
+    let value = ValueSeed::new().deserialize(&input).unwrap();
+    assert_eq!(value, input);
+
+    let seed = ValueSeed::new().reject_non_finite(true);
+    assert!(seed.deserialize(&input).is_err());
+    assert!(seed.deserialize(&Value::F64(f64::INFINITY)).is_err());
+    assert_eq!(seed.deserialize(&Value::F64(1.5)).unwrap(), Value::F64(1.5));
+}
+
+#[test]
+fn unit_struct_names_are_not_stored() {
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Marker;
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Other;
+
+    let value = to_value(Marker).unwrap();
+    assert_eq!(value, Value::Unit);
+    assert_eq!(value.clone().deserialize_into::<Marker>().unwrap(), Marker);
+    assert_eq!(Other::deserialize(&value).unwrap(), Other);
+
+    let err = Value::String("Marker".into()).deserialize_into::<Marker>().unwrap_err();
+    assert!(err.to_string().contains("unit struct Marker"), "{}", err);
+}
+
+#[test]
+fn to_cow_str() {
+    match Value::String("hello".into()).to_cow_str() {
+        Some(Cow::Borrowed(s)) => assert_eq!(s, "hello"),
+        v => panic!("expected a borrowed str, got {:?}", v),
+    }
+    match Value::I32(-5).to_cow_str() {
+        Some(Cow::Owned(s)) => assert_eq!(s, "-5"),
+        v => panic!("expected an owned str, got {:?}", v),
+    }
+    assert_eq!(Value::Bool(true).to_cow_str().unwrap(), "true");
+    assert_eq!(Value::F64(1.5).to_cow_str().unwrap(), "1.5");
+    assert_eq!(Value::Char('x').to_cow_str().unwrap(), "x");
+    assert_eq!(Value::Bytes(b"hi".to_vec()).to_cow_str(), None);
+    assert_eq!(Value::Seq(vec![]).to_cow_str(), None);
+    assert_eq!(Value::Unit.to_cow_str(), None);
+}
+
+#[test]
+fn deserialize_tuple_struct_arity() {
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Rgb(u8, u8, u8);
+
+    let rgb = Value::Seq(vec![Value::U8(1), Value::U8(2), Value::U8(3)]);
+    assert_eq!(Rgb::deserialize(&rgb).unwrap(), Rgb(1, 2, 3));
+    assert_eq!(rgb.deserialize_into::<Rgb>().unwrap(), Rgb(1, 2, 3));
+
+    let short = Value::Seq(vec![Value::U8(1), Value::U8(2)]);
+    let err = short.deserialize_into::<Rgb>().unwrap_err();
+    assert_eq!(err.to_string(), "Invalid length 2. Expected tuple struct Rgb");
+
+    let long = Value::Seq(vec![Value::U8(1), Value::U8(2), Value::U8(3), Value::U8(4)]);
+    let err = Rgb::deserialize(&long).unwrap_err();
+    assert_eq!(err.to_string(), "Invalid length 4. Expected tuple struct Rgb");
+
+    let de = ValueDeserializer::<DeserializerError>::new(long).ignore_extra_tuple_elements(true);
+    assert_eq!(Rgb::deserialize(de).unwrap(), Rgb(1, 2, 3));
+}
+
+#[cfg(feature = "hex")]
+#[test]
+fn hex_round_trip() {
+    let bytes = Value::Bytes(vec![0x00, 0xab, 0x10, 0xff]);
+    assert_eq!(bytes.to_hex().unwrap(), "00ab10ff");
+    assert_eq!(Value::from_hex("00ab10ff").unwrap(), bytes);
+    assert_eq!(Value::from_hex("0x00AB10FF").unwrap(), bytes);
+    assert_eq!(Value::from_hex("").unwrap(), Value::Bytes(vec![]));
+    assert_eq!(Value::String("00".into()).to_hex(), None);
+
+    assert_eq!(Value::from_hex("0xabc"), Err(HexError::OddLength(3)));
+    assert_eq!(Value::from_hex("zz"), Err(HexError::InvalidChar('z')));
+}
+
+#[test]
+fn deserialize_max_ops() {
+    let row = Value::Seq((0..10).map(Value::U32).collect());
+    let value = Value::Seq(vec![row; 10]);
+
+    let de = ValueDeserializer::<DeserializerError>::new(value.clone()).max_ops(110);
+    assert_eq!(Vec::<Vec<u32>>::deserialize(de).unwrap().len(), 10);
+
+    let de = ValueDeserializer::<DeserializerError>::new(value.clone()).max_ops(50);
+    let err = Vec::<Vec<u32>>::deserialize(de).unwrap_err();
+    assert_eq!(err.to_string(), "operation limit of 50 exceeded");
+
+    let de = ValueRefDeserializer::<DeserializerError>::new(&value).max_ops(109);
+    assert!(Vec::<Vec<u32>>::deserialize(de).is_err());
+}
+
+#[test]
+fn signed_zero_map_keys_coalesce() {
+    // OrderedFloat treats -0.0 and 0.0 as equal and hashes them alike, so
+    // they already share a single map entry
+    assert_eq!(Value::F64(-0.0), Value::F64(0.0));
+    assert_eq!(Value::F32(-0.0).cmp(&Value::F32(0.0)), Ordering::Equal);
+
+    let mut map = ValueMap::new();
+    map.insert(Value::F64(0.0), Value::U8(1));
+    map.insert(Value::F64(-0.0), Value::U8(2));
+    assert_eq!(map.len(), 1);
+    assert_eq!(map[&Value::F64(0.0)], Value::U8(2));
+
+    let pairs = vec![(Value::F64(0.0), Value::U8(1)), (Value::F64(-0.0), Value::U8(2))];
+    assert_eq!(Value::from_pairs_checked(pairs), Err(DuplicateKeyError(Value::F64(-0.0))));
+}
+
+#[test]
+fn unknown_field_suggestion() {
+    #[derive(Deserialize, Debug)]
+    #[serde(deny_unknown_fields)]
+    #[allow(dead_code)]
+    struct Config {
+        host: String,
+        port: u16,
+    }
+
+    let value = Value::Map(vec![
+        (Value::String("host".into()), Value::String("localhost".into())),
+        (Value::String("prot".into()), Value::U16(80)),
+    ].into_iter().collect());
+    let err = value.deserialize_into::<Config>().unwrap_err();
+    assert_eq!(err.to_string(), "Unknown field prot. Expected one of host, port. Did you mean port?");
+
+    let value = Value::Map(vec![
+        (Value::String("timeout".into()), Value::U16(80)),
+    ].into_iter().collect());
+    let err = Config::deserialize(&value).unwrap_err();
+    assert_eq!(err.to_string(), "Unknown field timeout. Expected one of host, port");
+}
+
+#[test]
+fn value_map_api() {
+    let mut btree = BTreeMap::new();
+    btree.insert(Value::String("b".into()), Value::U8(2));
+    btree.insert(Value::String("a".into()), Value::U8(1));
+    let mut map = ValueMap::from(btree.clone());
+    assert_eq!(Value::from(btree.clone()), Value::Map(map.clone()));
+
+    *map.entry(Value::String("c".into())).or_insert(Value::Unit) = Value::U8(3);
+    assert_eq!(map.len(), 3);
+    assert_eq!(map[&Value::String("c".into())], Value::U8(3));
+    assert_eq!(map.remove(&Value::String("c".into())), Some(Value::U8(3)));
+    map.values_mut().for_each(|v| *v = Value::Bool(true));
+    assert!(map.values().all(|v| *v == Value::Bool(true)));
+    assert_eq!(map.keys().count(), 2);
+    assert_eq!(BTreeMap::from(map.clone()).len(), 2);
+
+    let a = Value::String("a".into());
+    map.entry(a.clone()).and_modify(|v| *v = Value::U8(10)).or_default();
+    assert_eq!(map[&a], Value::U8(10));
+    assert_eq!(*map.entry(Value::String("d".into())).and_modify(|_| unreachable!()).or_default(), Value::Unit);
+    match map.entry(a.clone()) {
+        map::Entry::Occupied(mut entry) => {
+            assert_eq!(entry.insert(Value::U8(11)), Value::U8(10));
+            assert_eq!(entry.remove_entry(), (a.clone(), Value::U8(11)));
+        },
+        map::Entry::Vacant(..) => unreachable!(),
+    }
+    match map.entry(a.clone()) {
+        map::Entry::Vacant(entry) => assert_eq!(*entry.insert(Value::U8(12)), Value::U8(12)),
+        map::Entry::Occupied(..) => unreachable!(),
+    }
+    assert_eq!(map.len(), 3);
+}
@@ -0,0 +1,400 @@
+//! The map type held by `Value::Map`.
+//!
+//! `ValueMap` keeps its entries sorted by key by default, or in insertion
+//! order with the `preserve_order` feature. The backing collection is
+//! private so that enabling the feature does not change any public type.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::iter::{FromIterator, FusedIterator};
+use std::ops;
+
+use serde::ser;
+
+use crate::Value;
+
+#[cfg(not(feature = "preserve_order"))]
+type MapImpl = BTreeMap<Value, Value>;
+#[cfg(feature = "preserve_order")]
+type MapImpl = indexmap::IndexMap<Value, Value>;
+
+// both backends name their entry types alike
+#[cfg(not(feature = "preserve_order"))]
+use std::collections::btree_map as map_impl;
+#[cfg(feature = "preserve_order")]
+use indexmap::map as map_impl;
+
+/// A map of `Value` keys to `Value`s, with the same API regardless of
+/// whether `preserve_order` is enabled.
+///
+/// Maps compare and hash by their entries sorted by key, so two maps with
+/// the same entries are equal even if they were inserted in a different
+/// order.
+#[derive(Clone, Default)]
+pub struct ValueMap {
+    pub(crate) map: MapImpl,
+}
+
+impl ValueMap {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.map.clear()
+    }
+
+    pub fn get(&self, key: &Value) -> Option<&Value> {
+        self.map.get(key)
+    }
+
+    pub fn get_mut(&mut self, key: &Value) -> Option<&mut Value> {
+        self.map.get_mut(key)
+    }
+
+    pub fn contains_key(&self, key: &Value) -> bool {
+        self.map.contains_key(key)
+    }
+
+    /// Inserts an entry, returning the previous value for `key`. A replaced
+    /// entry keeps its original key and position.
+    pub fn insert(&mut self, key: Value, value: Value) -> Option<Value> {
+        self.map.insert(key, value)
+    }
+
+    /// Removes an entry, keeping the order of the others.
+    pub fn remove(&mut self, key: &Value) -> Option<Value> {
+        #[cfg(not(feature = "preserve_order"))]
+        return self.map.remove(key);
+        #[cfg(feature = "preserve_order")]
+        return self.map.shift_remove(key);
+    }
+
+    /// Keeps only the entries for which `f` returns `true`, in their
+    /// existing order.
+    pub fn retain<F: FnMut(&Value, &mut Value) -> bool>(&mut self, f: F) {
+        self.map.retain(f)
+    }
+
+    pub fn entry(&mut self, key: Value) -> Entry<'_> {
+        match self.map.entry(key) {
+            map_impl::Entry::Vacant(entry) => Entry::Vacant(VacantEntry(entry)),
+            map_impl::Entry::Occupied(entry) => Entry::Occupied(OccupiedEntry(entry)),
+        }
+    }
+
+    pub fn iter(&self) -> Iter<'_> {
+        Iter(self.map.iter())
+    }
+
+    pub fn iter_mut(&mut self) -> IterMut<'_> {
+        IterMut(self.map.iter_mut())
+    }
+
+    pub fn keys(&self) -> Keys<'_> {
+        Keys(self.map.keys())
+    }
+
+    pub fn values(&self) -> Values<'_> {
+        Values(self.map.values())
+    }
+
+    pub fn values_mut(&mut self) -> ValuesMut<'_> {
+        ValuesMut(self.map.values_mut())
+    }
+
+    // the entries sorted by key, whatever order the map keeps them in
+    #[cfg(not(feature = "preserve_order"))]
+    pub(crate) fn sorted_entries(&self) -> Iter<'_> {
+        self.iter()
+    }
+
+    #[cfg(feature = "preserve_order")]
+    pub(crate) fn sorted_entries(&self) -> std::vec::IntoIter<(&Value, &Value)> {
+        let mut entries: Vec<_> = self.iter().collect();
+        entries.sort_by_key(|&(k, _)| k);
+        entries.into_iter()
+    }
+}
+
+/// A view into a single entry of a `ValueMap`, as returned by
+/// `ValueMap::entry`.
+pub enum Entry<'a> {
+    Vacant(VacantEntry<'a>),
+    Occupied(OccupiedEntry<'a>),
+}
+
+/// An entry of a `ValueMap` with no value yet.
+pub struct VacantEntry<'a>(map_impl::VacantEntry<'a, Value, Value>);
+
+/// An entry of a `ValueMap` that holds a value.
+pub struct OccupiedEntry<'a>(map_impl::OccupiedEntry<'a, Value, Value>);
+
+impl<'a> Entry<'a> {
+    pub fn key(&self) -> &Value {
+        match *self {
+            Entry::Vacant(ref entry) => entry.key(),
+            Entry::Occupied(ref entry) => entry.key(),
+        }
+    }
+
+    pub fn or_insert(self, default: Value) -> &'a mut Value {
+        self.or_insert_with(|| default)
+    }
+
+    pub fn or_insert_with<F: FnOnce() -> Value>(self, default: F) -> &'a mut Value {
+        match self {
+            Entry::Vacant(entry) => entry.insert(default()),
+            Entry::Occupied(entry) => entry.into_mut(),
+        }
+    }
+
+    /// Inserts `Value::Unit` if the entry is vacant.
+    pub fn or_default(self) -> &'a mut Value {
+        self.or_insert_with(Value::default)
+    }
+
+    /// Calls `f` with the value if the entry is occupied.
+    pub fn and_modify<F: FnOnce(&mut Value)>(self, f: F) -> Self {
+        match self {
+            Entry::Occupied(mut entry) => {
+                f(entry.get_mut());
+                Entry::Occupied(entry)
+            },
+            entry => entry,
+        }
+    }
+}
+
+impl<'a> VacantEntry<'a> {
+    pub fn key(&self) -> &Value {
+        self.0.key()
+    }
+
+    pub fn into_key(self) -> Value {
+        self.0.into_key()
+    }
+
+    /// Inserts `value`, at the end of the map with `preserve_order`.
+    pub fn insert(self, value: Value) -> &'a mut Value {
+        self.0.insert(value)
+    }
+}
+
+impl<'a> OccupiedEntry<'a> {
+    pub fn key(&self) -> &Value {
+        self.0.key()
+    }
+
+    pub fn get(&self) -> &Value {
+        self.0.get()
+    }
+
+    pub fn get_mut(&mut self) -> &mut Value {
+        self.0.get_mut()
+    }
+
+    pub fn into_mut(self) -> &'a mut Value {
+        self.0.into_mut()
+    }
+
+    /// Replaces the value, returning the old one. The entry keeps its
+    /// original key and position.
+    pub fn insert(&mut self, value: Value) -> Value {
+        self.0.insert(value)
+    }
+
+    /// Removes the entry, keeping the order of the others.
+    pub fn remove(self) -> Value {
+        self.remove_entry().1
+    }
+
+    /// Removes the entry, keeping the order of the others, and returns its
+    /// key and value.
+    pub fn remove_entry(self) -> (Value, Value) {
+        #[cfg(not(feature = "preserve_order"))]
+        return self.0.remove_entry();
+        #[cfg(feature = "preserve_order")]
+        return self.0.shift_remove_entry();
+    }
+}
+
+impl fmt::Debug for ValueMap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.map.fmt(f)
+    }
+}
+
+impl PartialEq for ValueMap {
+    fn eq(&self, rhs: &Self) -> bool {
+        self.len() == rhs.len() && self.sorted_entries().eq(rhs.sorted_entries())
+    }
+}
+
+impl Eq for ValueMap { }
+
+impl PartialOrd for ValueMap {
+    fn partial_cmp(&self, rhs: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(rhs))
+    }
+}
+
+impl Ord for ValueMap {
+    fn cmp(&self, rhs: &Self) -> std::cmp::Ordering {
+        self.sorted_entries().cmp(rhs.sorted_entries())
+    }
+}
+
+impl Hash for ValueMap {
+    fn hash<H: Hasher>(&self, hasher: &mut H) {
+        self.len().hash(hasher);
+        self.sorted_entries().for_each(|e| e.hash(hasher));
+    }
+}
+
+impl ops::Index<&Value> for ValueMap {
+    type Output = Value;
+
+    fn index(&self, key: &Value) -> &Value {
+        self.map.index(key)
+    }
+}
+
+impl ser::Serialize for ValueMap {
+    fn serialize<S: ser::Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        s.collect_map(self.iter())
+    }
+}
+
+impl FromIterator<(Value, Value)> for ValueMap {
+    fn from_iter<I: IntoIterator<Item = (Value, Value)>>(iter: I) -> Self {
+        ValueMap { map: iter.into_iter().collect() }
+    }
+}
+
+impl Extend<(Value, Value)> for ValueMap {
+    fn extend<I: IntoIterator<Item = (Value, Value)>>(&mut self, iter: I) {
+        self.map.extend(iter)
+    }
+}
+
+impl From<BTreeMap<Value, Value>> for ValueMap {
+    fn from(map: BTreeMap<Value, Value>) -> Self {
+        map.into_iter().collect()
+    }
+}
+
+impl From<ValueMap> for BTreeMap<Value, Value> {
+    fn from(map: ValueMap) -> Self {
+        map.into_iter().collect()
+    }
+}
+
+impl IntoIterator for ValueMap {
+    type Item = (Value, Value);
+    type IntoIter = IntoIter;
+
+    fn into_iter(self) -> IntoIter {
+        IntoIter(self.map.into_iter())
+    }
+}
+
+impl<'a> IntoIterator for &'a ValueMap {
+    type Item = (&'a Value, &'a Value);
+    type IntoIter = Iter<'a>;
+
+    fn into_iter(self) -> Iter<'a> {
+        self.iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a mut ValueMap {
+    type Item = (&'a Value, &'a mut Value);
+    type IntoIter = IterMut<'a>;
+
+    fn into_iter(self) -> IterMut<'a> {
+        self.iter_mut()
+    }
+}
+
+macro_rules! map_iterator {
+    ($(#[$attr:meta])* $name:ident<$($lt:lifetime)?>($btree:ty, $indexmap:ty) => $item:ty) => {
+        $(#[$attr])*
+        #[cfg(not(feature = "preserve_order"))]
+        pub struct $name<$($lt)?>($btree);
+        $(#[$attr])*
+        #[cfg(feature = "preserve_order")]
+        pub struct $name<$($lt)?>($indexmap);
+
+        impl<$($lt)?> Iterator for $name<$($lt)?> {
+            type Item = $item;
+
+            fn next(&mut self) -> Option<Self::Item> {
+                self.0.next()
+            }
+
+            fn size_hint(&self) -> (usize, Option<usize>) {
+                self.0.size_hint()
+            }
+        }
+
+        impl<$($lt)?> DoubleEndedIterator for $name<$($lt)?> {
+            fn next_back(&mut self) -> Option<Self::Item> {
+                self.0.next_back()
+            }
+        }
+
+        impl<$($lt)?> ExactSizeIterator for $name<$($lt)?> {
+            fn len(&self) -> usize {
+                self.0.len()
+            }
+        }
+
+        impl<$($lt)?> FusedIterator for $name<$($lt)?> { }
+    };
+}
+
+map_iterator! {
+    /// An iterator over the entries of a `ValueMap`.
+    Iter<'a>(std::collections::btree_map::Iter<'a, Value, Value>, indexmap::map::Iter<'a, Value, Value>)
+        => (&'a Value, &'a Value)
+}
+
+map_iterator! {
+    /// A mutable iterator over the entries of a `ValueMap`.
+    IterMut<'a>(std::collections::btree_map::IterMut<'a, Value, Value>, indexmap::map::IterMut<'a, Value, Value>)
+        => (&'a Value, &'a mut Value)
+}
+
+map_iterator! {
+    /// An owning iterator over the entries of a `ValueMap`.
+    IntoIter<>(std::collections::btree_map::IntoIter<Value, Value>, indexmap::map::IntoIter<Value, Value>)
+        => (Value, Value)
+}
+
+map_iterator! {
+    /// An iterator over the keys of a `ValueMap`.
+    Keys<'a>(std::collections::btree_map::Keys<'a, Value, Value>, indexmap::map::Keys<'a, Value, Value>)
+        => &'a Value
+}
+
+map_iterator! {
+    /// An iterator over the values of a `ValueMap`.
+    Values<'a>(std::collections::btree_map::Values<'a, Value, Value>, indexmap::map::Values<'a, Value, Value>)
+        => &'a Value
+}
+
+map_iterator! {
+    /// A mutable iterator over the values of a `ValueMap`.
+    ValuesMut<'a>(std::collections::btree_map::ValuesMut<'a, Value, Value>, indexmap::map::ValuesMut<'a, Value, Value>)
+        => &'a mut Value
+}
@@ -0,0 +1,136 @@
+use std::error::Error;
+use std::fmt;
+
+use crate::path::{pointer_index, pointer_segments, transparent_mut};
+use crate::{PathError, Value};
+
+/// A single [RFC 6902](https://tools.ietf.org/html/rfc6902) JSON Patch
+/// operation. Every `path` and `from` is a JSON Pointer.
+#[derive(Clone, Debug, PartialEq)]
+pub enum PatchOp {
+    /// Inserts `value` into a seq, shifting later elements, or sets a map
+    /// entry. A final segment of `-` appends to a seq.
+    Add { path: String, value: Value },
+    /// Removes an existing value.
+    Remove { path: String },
+    /// Replaces an existing value.
+    Replace { path: String, value: Value },
+    /// Removes the value at `from` and adds it at `path`.
+    Move { from: String, path: String },
+    /// Adds a clone of the value at `from` at `path`.
+    Copy { from: String, path: String },
+    /// Checks that the value at `path` equals `value`.
+    Test { path: String, value: Value },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum PatchError {
+    /// A pointer is invalid or does not address a value it needs to.
+    Path(PathError),
+    /// A `test` operation found a different value at the given pointer.
+    TestFailed(String),
+    /// A `move` operation would move a value into one of its own children.
+    MoveIntoChild(String),
+}
+
+impl fmt::Display for PatchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PatchError::Path(ref e) => e.fmt(f),
+            PatchError::TestFailed(ref pointer) => write!(f, "Test failed at {:?}", pointer),
+            PatchError::MoveIntoChild(ref pointer) => write!(f, "Cannot move {:?} into one of its children", pointer),
+        }
+    }
+}
+
+impl Error for PatchError {
+    fn description(&self) -> &str {
+        "Value patch error"
+    }
+}
+
+impl From<PathError> for PatchError {
+    fn from(e: PathError) -> Self {
+        PatchError::Path(e)
+    }
+}
+
+fn last_segment(pointer: &str) -> String {
+    pointer.rsplit('/').next().unwrap_or_default().to_owned()
+}
+
+impl Value {
+    /// Applies an [RFC 6902](https://tools.ietf.org/html/rfc6902) JSON Patch
+    /// to `self`.
+    ///
+    /// The operations are applied in order to a copy of `self`, which only
+    /// replaces `self` once every operation has succeeded, so a failing
+    /// operation leaves `self` unchanged.
+    pub fn apply_json_patch(&mut self, ops: &[PatchOp]) -> Result<(), PatchError> {
+        let mut patched = self.clone();
+        for op in ops {
+            patched.apply_patch_op(op)?;
+        }
+        *self = patched;
+        Ok(())
+    }
+
+    fn apply_patch_op(&mut self, op: &PatchOp) -> Result<(), PatchError> {
+        match *op {
+            PatchOp::Add { ref path, ref value } => self.patch_add(path, value.clone()),
+            PatchOp::Remove { ref path } => self.patch_remove(path).map(drop),
+            PatchOp::Replace { ref path, ref value } => {
+                let target = self.pointer_mut(path).ok_or_else(|| PathError::Missing(last_segment(path)))?;
+                *target = value.clone();
+                Ok(())
+            },
+            PatchOp::Move { ref from, ref path } => {
+                if path.starts_with(from.as_str()) && path[from.len()..].starts_with('/') {
+                    return Err(PatchError::MoveIntoChild(from.clone()))
+                }
+                let value = self.patch_remove(from)?;
+                self.patch_add(path, value)
+            },
+            PatchOp::Copy { ref from, ref path } => {
+                let value = self.pointer(from).ok_or_else(|| PathError::Missing(last_segment(from)))?.clone();
+                self.patch_add(path, value)
+            },
+            PatchOp::Test { ref path, ref value } => match self.pointer(path) {
+                Some(v) if v == value => Ok(()),
+                Some(..) => Err(PatchError::TestFailed(path.clone())),
+                None => Err(PathError::Missing(last_segment(path)).into()),
+            },
+        }
+    }
+
+    fn patch_remove(&mut self, pointer: &str) -> Result<Value, PatchError> {
+        pointer_segments(pointer)?;
+        self.remove(pointer).ok_or_else(|| PathError::Missing(last_segment(pointer)).into())
+    }
+
+    fn patch_add(&mut self, pointer: &str, value: Value) -> Result<(), PatchError> {
+        let mut segments = pointer_segments(pointer)?;
+        let last = match segments.pop() {
+            Some(last) => last,
+            None => {
+                *self = value;
+                return Ok(())
+            },
+        };
+
+        let parent = segments.last().cloned().unwrap_or_default();
+        let parent = self.segments_mut(segments).ok_or(PathError::Missing(parent))?;
+        match *transparent_mut(parent) {
+            Value::Map(ref mut map) => {
+                map.insert(Value::String(last), value);
+            },
+            Value::Seq(ref mut seq) if last == "-" => seq.push(value),
+            Value::Seq(ref mut seq) => match pointer_index(&last) {
+                Some(index) if index <= seq.len() => seq.insert(index, value),
+                _ => return Err(PathError::InvalidIndex(last).into()),
+            },
+            _ => return Err(PathError::NotContainer(last).into()),
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,302 @@
+use std::error::Error;
+use std::fmt;
+use std::mem;
+
+use crate::{Value, ValueMap};
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum PathSegment {
+    Key(Value),
+    Index(usize),
+}
+
+/// A location within a `Value` tree, from the root down.
+///
+/// `Value::Option` and `Value::Newtype` wrappers are transparent and do not
+/// contribute a segment.
+pub type Path = Vec<PathSegment>;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PathError {
+    /// The pointer is not empty and does not start with `/`.
+    InvalidPointer(String),
+    /// No value exists at the named segment.
+    Missing(String),
+    /// The named segment addresses a seq but is not a valid index.
+    InvalidIndex(String),
+    /// The named segment addresses a value that is neither a map nor a seq.
+    NotContainer(String),
+}
+
+impl fmt::Display for PathError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PathError::InvalidPointer(ref pointer) => write!(f, "Invalid JSON pointer {:?}", pointer),
+            PathError::Missing(ref segment) => write!(f, "Missing value at segment {:?}", segment),
+            PathError::InvalidIndex(ref segment) => write!(f, "Invalid seq index {:?}", segment),
+            PathError::NotContainer(ref segment) => write!(f, "Cannot index into a scalar at segment {:?}", segment),
+        }
+    }
+}
+
+impl Error for PathError {
+    fn description(&self) -> &str {
+        "Value path error"
+    }
+}
+
+pub(crate) fn pointer_segments(pointer: &str) -> Result<Vec<String>, PathError> {
+    if pointer.is_empty() {
+        return Ok(Vec::new())
+    }
+    if !pointer.starts_with('/') {
+        return Err(PathError::InvalidPointer(pointer.into()))
+    }
+    Ok(pointer[1..].split('/').map(|s| s.replace("~1", "/").replace("~0", "~")).collect())
+}
+
+pub(crate) fn pointer_index(segment: &str) -> Option<usize> {
+    if segment.starts_with('+') || (segment.starts_with('0') && segment.len() > 1) {
+        return None
+    }
+    segment.parse().ok()
+}
+
+fn transparent(value: &Value) -> &Value {
+    match *value {
+        Value::Option(Some(ref v)) | Value::Newtype(ref v) => transparent(v),
+        ref v => v,
+    }
+}
+
+pub(crate) fn transparent_mut(value: &mut Value) -> &mut Value {
+    match *value {
+        Value::Option(Some(ref mut v)) | Value::Newtype(ref mut v) => transparent_mut(v),
+        ref mut v => v,
+    }
+}
+
+impl Value {
+    /// Returns the path of every map entry whose key is not a `Value::String`.
+    ///
+    /// Formats such as JSON and TOML only accept string keys, so this can be
+    /// used to validate a tree before handing it to their serializers. Each
+    /// path addresses the offending entry, so its parent is the map.
+    pub fn non_string_keys(&self) -> Vec<Path> {
+        let mut paths = Vec::new();
+        self.non_string_keys_at(&mut Vec::new(), &mut paths);
+        paths
+    }
+
+    fn non_string_keys_at(&self, path: &mut Path, paths: &mut Vec<Path>) {
+        match *self {
+            Value::Option(Some(ref v)) | Value::Newtype(ref v) => v.non_string_keys_at(path, paths),
+            Value::Seq(ref v) => for (i, v) in v.iter().enumerate() {
+                path.push(PathSegment::Index(i));
+                v.non_string_keys_at(path, paths);
+                path.pop();
+            },
+            Value::Map(ref v) => for (k, v) in v {
+                path.push(PathSegment::Key(k.clone()));
+                match *k {
+                    Value::String(..) => (),
+                    _ => paths.push(path.clone()),
+                }
+                v.non_string_keys_at(path, paths);
+                path.pop();
+            },
+            _ => (),
+        }
+    }
+
+    /// Returns the path of every node, including `self`, for which `pred`
+    /// returns `true`. Parents come before their children, and map keys are
+    /// not inspected.
+    pub fn find_paths<F: Fn(&Value) -> bool>(&self, pred: F) -> Vec<Path> {
+        let mut paths = Vec::new();
+        self.find_paths_at(&pred, &mut Vec::new(), &mut paths);
+        paths
+    }
+
+    fn find_paths_at<F: Fn(&Value) -> bool>(&self, pred: &F, path: &mut Path, paths: &mut Vec<Path>) {
+        if pred(self) {
+            paths.push(path.clone());
+        }
+        match *self {
+            Value::Option(Some(ref v)) | Value::Newtype(ref v) => v.find_paths_at(pred, path, paths),
+            Value::Seq(ref v) => for (i, v) in v.iter().enumerate() {
+                path.push(PathSegment::Index(i));
+                v.find_paths_at(pred, path, paths);
+                path.pop();
+            },
+            Value::Map(ref v) => for (k, v) in v {
+                path.push(PathSegment::Key(k.clone()));
+                v.find_paths_at(pred, path, paths);
+                path.pop();
+            },
+            _ => (),
+        }
+    }
+
+    /// Returns the path of every `F32` or `F64` that is NaN or infinite.
+    ///
+    /// Map keys are not inspected.
+    pub fn find_non_finite(&self) -> Vec<Path> {
+        let mut paths = Vec::new();
+        self.find_non_finite_at(&mut Vec::new(), &mut paths);
+        paths
+    }
+
+    fn find_non_finite_at(&self, path: &mut Path, paths: &mut Vec<Path>) {
+        match *self {
+            Value::F32(v) if !v.is_finite() => paths.push(path.clone()),
+            Value::F64(v) if !v.is_finite() => paths.push(path.clone()),
+            Value::Option(Some(ref v)) | Value::Newtype(ref v) => v.find_non_finite_at(path, paths),
+            Value::Seq(ref v) => for (i, v) in v.iter().enumerate() {
+                path.push(PathSegment::Index(i));
+                v.find_non_finite_at(path, paths);
+                path.pop();
+            },
+            Value::Map(ref v) => for (k, v) in v {
+                path.push(PathSegment::Key(k.clone()));
+                v.find_non_finite_at(path, paths);
+                path.pop();
+            },
+            _ => (),
+        }
+    }
+
+    /// Replaces every `F32` or `F64` that is NaN or infinite with a clone of
+    /// `replacement`, such as `Value::Unit` before exporting to JSON.
+    ///
+    /// Map keys are left untouched.
+    pub fn replace_non_finite(&mut self, replacement: Value) {
+        self.replace_non_finite_with(&replacement)
+    }
+
+    fn replace_non_finite_with(&mut self, replacement: &Value) {
+        match *self {
+            Value::F32(v) if !v.is_finite() => *self = replacement.clone(),
+            Value::F64(v) if !v.is_finite() => *self = replacement.clone(),
+            Value::Option(Some(ref mut v)) | Value::Newtype(ref mut v) => v.replace_non_finite_with(replacement),
+            Value::Seq(ref mut v) => v.iter_mut().for_each(|v| v.replace_non_finite_with(replacement)),
+            Value::Map(ref mut v) => v.values_mut().for_each(|v| v.replace_non_finite_with(replacement)),
+            _ => (),
+        }
+    }
+}
+
+impl Value {
+    /// Looks up a value by [JSON Pointer](https://tools.ietf.org/html/rfc6901).
+    ///
+    /// Map segments match `Value::String` keys, and seq segments are
+    /// decimal indices. `Value::Option(Some(..))` and `Value::Newtype`
+    /// wrappers are looked through.
+    pub fn pointer(&self, pointer: &str) -> Option<&Value> {
+        let mut value = self;
+        for segment in pointer_segments(pointer).ok()? {
+            value = match *transparent(value) {
+                Value::Map(ref map) => map.get(&Value::String(segment))?,
+                Value::Seq(ref seq) => seq.get(pointer_index(&segment)?)?,
+                _ => return None,
+            };
+        }
+        Some(value)
+    }
+
+    /// Looks up a value by JSON Pointer, as with `Value::pointer`.
+    pub fn pointer_mut(&mut self, pointer: &str) -> Option<&mut Value> {
+        self.segments_mut(pointer_segments(pointer).ok()?)
+    }
+
+    pub(crate) fn segments_mut(&mut self, segments: Vec<String>) -> Option<&mut Value> {
+        let mut value = self;
+        for segment in segments {
+            value = match *transparent_mut(value) {
+                Value::Map(ref mut map) => map.get_mut(&Value::String(segment))?,
+                Value::Seq(ref mut seq) => seq.get_mut(pointer_index(&segment)?)?,
+                _ => return None,
+            };
+        }
+        Some(value)
+    }
+
+    /// Removes and returns the value addressed by a JSON Pointer.
+    ///
+    /// Seq elements following a removed element are shifted down, and the
+    /// remaining keys of a map keep their order: sorted by key by default,
+    /// or insertion order with the `preserve_order` feature. Removing the
+    /// root replaces `self` with `Value::Unit`.
+    pub fn remove(&mut self, pointer: &str) -> Option<Value> {
+        let mut segments = pointer_segments(pointer).ok()?;
+        let last = match segments.pop() {
+            Some(last) => last,
+            None => return Some(mem::replace(self, Value::Unit)),
+        };
+
+        match *transparent_mut(self.segments_mut(segments)?) {
+            Value::Map(ref mut map) => map.remove(&Value::String(last)),
+            Value::Seq(ref mut seq) => {
+                let index = pointer_index(&last)?;
+                if index < seq.len() {
+                    Some(seq.remove(index))
+                } else {
+                    None
+                }
+            },
+            _ => None,
+        }
+    }
+
+    /// Replaces the value addressed by a JSON Pointer.
+    ///
+    /// The final segment may name a key missing from its map, which is then
+    /// inserted, or `-` to append to a seq. Missing intermediate values are an
+    /// error unless `create` is set, in which case empty maps are inserted
+    /// for them.
+    pub fn set(&mut self, pointer: &str, new: Value, create: bool) -> Result<(), PathError> {
+        let mut segments = pointer_segments(pointer)?;
+        let last = match segments.pop() {
+            Some(last) => last,
+            None => {
+                *self = new;
+                return Ok(())
+            },
+        };
+
+        let mut value = self;
+        for segment in segments {
+            value = match *transparent_mut(value) {
+                Value::Map(ref mut map) => {
+                    let key = Value::String(segment.clone());
+                    if !map.contains_key(&key) && !create {
+                        return Err(PathError::Missing(segment))
+                    }
+                    map.entry(key).or_insert_with(|| Value::Map(ValueMap::new()))
+                },
+                Value::Seq(ref mut seq) => {
+                    let index = pointer_index(&segment).ok_or_else(|| PathError::InvalidIndex(segment.clone()))?;
+                    seq.get_mut(index).ok_or(PathError::Missing(segment))?
+                },
+                _ => return Err(PathError::NotContainer(segment)),
+            };
+        }
+
+        match *transparent_mut(value) {
+            Value::Map(ref mut map) => {
+                map.insert(Value::String(last), new);
+            },
+            Value::Seq(ref mut seq) if last == "-" => seq.push(new),
+            Value::Seq(ref mut seq) => {
+                let index = pointer_index(&last).ok_or_else(|| PathError::InvalidIndex(last.clone()))?;
+                if index == seq.len() {
+                    seq.push(new);
+                } else {
+                    *seq.get_mut(index).ok_or(PathError::Missing(last))? = new;
+                }
+            },
+            _ => return Err(PathError::NotContainer(last)),
+        }
+        Ok(())
+    }
+}
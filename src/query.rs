@@ -0,0 +1,118 @@
+//! `application/x-www-form-urlencoded` conversions.
+//!
+//! Nested maps are flattened into bracketed keys, so `{"a": {"b": 1}}`
+//! becomes `a[b]=1`, and seq elements use their index as the key, as in
+//! `a[0]=x&a[1]=y`. Every scalar is written as a string. Empty maps and
+//! seqs and `Option(None)` produce no pairs at all and so do not survive a
+//! round trip.
+
+use serde::ser::Error;
+
+use crate::{insert_nested, SerializerError, Value, ValueMap};
+
+impl Value {
+    /// Encodes a map as a query string, returning an error if `self` is not
+    /// a map or contains non-string keys or bytes.
+    pub fn to_query_string(&self) -> Result<String, SerializerError> {
+        let map = match *self {
+            Value::Map(ref map) => map,
+            _ => return Err(SerializerError::custom("query strings can only be built from a map")),
+        };
+        let mut serializer = form_urlencoded::Serializer::new(String::new());
+        for (k, v) in map {
+            append_pair(&mut serializer, key_str(k)?.to_owned(), v)?;
+        }
+        Ok(serializer.finish())
+    }
+
+    /// Decodes a query string into a map of strings, nesting bracketed keys.
+    ///
+    /// A nested map whose keys are exactly `0` to `n - 1` becomes a seq.
+    /// When a key repeats, the last value wins, so PHP-style `a[]=` keys
+    /// are not collected into a seq.
+    pub fn from_query_string(s: &str) -> Value {
+        let mut root = ValueMap::new();
+        for (key, value) in form_urlencoded::parse(s.as_bytes()) {
+            insert_nested(&mut root, key_segments(&key).into_iter(), Value::String(value.into_owned()));
+        }
+        seqs_from_indices(Value::Map(root))
+    }
+}
+
+fn key_str(key: &Value) -> Result<&str, SerializerError> {
+    match *key {
+        Value::String(ref key) => Ok(key),
+        ref key => Err(SerializerError::KeyMustBeString(key.clone())),
+    }
+}
+
+fn append_pair(serializer: &mut form_urlencoded::Serializer<String>, key: String, value: &Value) -> Result<(), SerializerError> {
+    let value = match *value {
+        Value::Map(ref map) => {
+            for (k, v) in map {
+                append_pair(serializer, format!("{}[{}]", key, key_str(k)?), v)?;
+            }
+            return Ok(())
+        },
+        Value::Seq(ref seq) => {
+            for (i, v) in seq.iter().enumerate() {
+                append_pair(serializer, format!("{}[{}]", key, i), v)?;
+            }
+            return Ok(())
+        },
+        Value::Option(None) => return Ok(()),
+        Value::Option(Some(ref v)) | Value::Newtype(ref v) => return append_pair(serializer, key, v),
+        Value::Bool(v) => v.to_string(),
+        Value::U8(v) => v.to_string(),
+        Value::U16(v) => v.to_string(),
+        Value::U32(v) => v.to_string(),
+        Value::U64(v) => v.to_string(),
+        Value::I8(v) => v.to_string(),
+        Value::I16(v) => v.to_string(),
+        Value::I32(v) => v.to_string(),
+        Value::I64(v) => v.to_string(),
+        Value::F32(v) => v.to_string(),
+        Value::F64(v) => v.to_string(),
+        Value::Char(v) => v.to_string(),
+        Value::String(ref v) => v.clone(),
+        Value::Unit => String::new(),
+        Value::Bytes(..) => return Err(SerializerError::custom(format_args!("bytes at {} cannot be encoded in a query string", key))),
+        Value::BigInt(..) => return Err(SerializerError::custom(format_args!("big integer at {} cannot be encoded in a query string", key))),
+    };
+    serializer.append_pair(&key, &value);
+    Ok(())
+}
+
+// splits `a[b][c]` into `a`, `b` and `c`, or keeps a malformed key whole
+fn key_segments(key: &str) -> Vec<&str> {
+    let (head, mut rest) = match key.find('[') {
+        Some(i) if i > 0 => key.split_at(i),
+        _ => return vec![key],
+    };
+    let mut segments = vec![head];
+    while !rest.is_empty() {
+        match (rest.starts_with('['), rest.find(']')) {
+            (true, Some(end)) => {
+                segments.push(&rest[1..end]);
+                rest = &rest[end + 1..];
+            },
+            _ => return vec![key],
+        }
+    }
+    segments
+}
+
+fn seqs_from_indices(value: Value) -> Value {
+    let map = match value {
+        Value::Map(map) => map,
+        value => return value,
+    };
+    let mut map: ValueMap = map.into_iter().map(|(k, v)| (k, seqs_from_indices(v))).collect();
+    let is_seq = !map.is_empty() && (0..map.len()).all(|i| map.contains_key(&Value::String(i.to_string())));
+    if !is_seq {
+        return Value::Map(map)
+    }
+    Value::Seq((0..map.len()).map(|i| {
+        map.remove(&Value::String(i.to_string())).unwrap_or(Value::Unit)
+    }).collect())
+}
@@ -1,19 +1,29 @@
 use serde::ser;
-use std::collections::BTreeMap;
+use std::cell::Cell;
 use std::error::Error;
 use std::fmt;
 
-use crate::Value;
+use crate::{map_value, Value, ValueMap, BIGINT_TOKEN};
 
 #[derive(Debug)]
 pub enum SerializerError {
+    /// An error reported by a `Serialize` implementation.
     Custom(String),
+    /// A map value was serialized without a preceding key.
+    MissingKey,
+    /// A map key was not a `Value::String` where only string keys are allowed.
+    KeyMustBeString(Value),
+    /// Serialization would produce more than the given number of nodes.
+    TooManyNodes(usize),
 }
 
 impl fmt::Display for SerializerError {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
         match *self {
             SerializerError::Custom(ref s) => fmt.write_str(s),
+            SerializerError::MissingKey => fmt.write_str("Map value serialized without a key"),
+            SerializerError::KeyMustBeString(ref key) => write!(fmt, "Map key must be a string, found {:?}", key),
+            SerializerError::TooManyNodes(max) => write!(fmt, "Value exceeds the limit of {} nodes", max),
         }
     }
 }
@@ -53,98 +63,160 @@ impl ser::Serialize for Value {
             Value::Seq(ref v) => v.serialize(s),
             Value::Map(ref v) => v.serialize(s),
             Value::Bytes(ref v) => s.serialize_bytes(v),
+            Value::BigInt(ref v) => {
+                let mut st = s.serialize_struct(BIGINT_TOKEN, 1)?;
+                ser::SerializeStruct::serialize_field(&mut st, BIGINT_TOKEN, &BigIntBytes(v))?;
+                ser::SerializeStruct::end(st)
+            },
         }
     }
 }
 
+struct BigIntBytes<'a>(&'a [u8]);
+
+impl<'a> ser::Serialize for BigIntBytes<'a> {
+    fn serialize<S: ser::Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_bytes(self.0)
+    }
+}
+
 pub fn to_value<T: ser::Serialize>(value: T) -> Result<Value, SerializerError> {
-    value.serialize(Serializer)
+    value.serialize(Serializer { budget: None })
+}
+
+/// Serializes `value` like `to_value`, but fails with
+/// `SerializerError::TooManyNodes` as soon as the result would contain more
+/// than `max_nodes` nodes, as counted by `Value::node_count`.
+///
+/// Serialization stops at the first node over the limit, so this can guard
+/// against building huge trees from untrusted input.
+pub fn to_value_bounded<T: ?Sized + ser::Serialize>(value: &T, max_nodes: usize) -> Result<Value, SerializerError> {
+    let budget = Budget {
+        remaining: Cell::new(max_nodes),
+        max_nodes,
+    };
+    value.serialize(Serializer { budget: Some(&budget) })
+}
+
+struct Budget {
+    remaining: Cell<usize>,
+    max_nodes: usize,
 }
 
-struct Serializer;
+#[derive(Clone, Copy)]
+struct Serializer<'a> {
+    budget: Option<&'a Budget>,
+}
+
+impl<'a> Serializer<'a> {
+    fn nodes(self, count: usize) -> Result<Self, SerializerError> {
+        if let Some(budget) = self.budget {
+            let remaining = budget.remaining.get().checked_sub(count)
+                .ok_or(SerializerError::TooManyNodes(budget.max_nodes))?;
+            budget.remaining.set(remaining);
+        }
+        Ok(self)
+    }
+}
 
-impl ser::Serializer for Serializer {
+impl<'a> ser::Serializer for Serializer<'a> {
     type Ok = Value;
     type Error = SerializerError;
-    type SerializeSeq = SerializeSeq;
-    type SerializeTuple = SerializeTuple;
-    type SerializeTupleStruct = SerializeTupleStruct;
-    type SerializeTupleVariant = SerializeTupleVariant;
-    type SerializeMap = SerializeMap;
-    type SerializeStruct = SerializeStruct;
-    type SerializeStructVariant = SerializeStructVariant;
+    type SerializeSeq = SerializeSeq<'a>;
+    type SerializeTuple = SerializeTuple<'a>;
+    type SerializeTupleStruct = SerializeTupleStruct<'a>;
+    type SerializeTupleVariant = SerializeTupleVariant<'a>;
+    type SerializeMap = SerializeMap<'a>;
+    type SerializeStruct = SerializeStruct<'a>;
+    type SerializeStructVariant = SerializeStructVariant<'a>;
 
     fn serialize_bool(self, v: bool) -> Result<Self::Ok, Self::Error> {
+        self.nodes(1)?;
         Ok(Value::Bool(v))
     }
 
     fn serialize_i8(self, v: i8) -> Result<Self::Ok, Self::Error> {
+        self.nodes(1)?;
         Ok(Value::I8(v))
     }
 
     fn serialize_i16(self, v: i16) -> Result<Self::Ok, Self::Error> {
+        self.nodes(1)?;
         Ok(Value::I16(v))
     }
 
     fn serialize_i32(self, v: i32) -> Result<Self::Ok, Self::Error> {
+        self.nodes(1)?;
         Ok(Value::I32(v))
     }
 
     fn serialize_i64(self, v: i64) -> Result<Self::Ok, Self::Error> {
+        self.nodes(1)?;
         Ok(Value::I64(v))
     }
 
     fn serialize_u8(self, v: u8) -> Result<Self::Ok, Self::Error> {
+        self.nodes(1)?;
         Ok(Value::U8(v))
     }
 
     fn serialize_u16(self, v: u16) -> Result<Self::Ok, Self::Error> {
+        self.nodes(1)?;
         Ok(Value::U16(v))
     }
 
     fn serialize_u32(self, v: u32) -> Result<Self::Ok, Self::Error> {
+        self.nodes(1)?;
         Ok(Value::U32(v))
     }
 
     fn serialize_u64(self, v: u64) -> Result<Self::Ok, Self::Error> {
+        self.nodes(1)?;
         Ok(Value::U64(v))
     }
 
     fn serialize_f32(self, v: f32) -> Result<Self::Ok, Self::Error> {
+        self.nodes(1)?;
         Ok(Value::F32(v))
     }
 
     fn serialize_f64(self, v: f64) -> Result<Self::Ok, Self::Error> {
+        self.nodes(1)?;
         Ok(Value::F64(v))
     }
 
     fn serialize_char(self, v: char) -> Result<Self::Ok, Self::Error> {
+        self.nodes(1)?;
         Ok(Value::Char(v))
     }
 
     fn serialize_str(self, v: &str) -> Result<Self::Ok, Self::Error> {
+        self.nodes(1)?;
         Ok(Value::String(v.to_string()))
     }
 
     fn serialize_bytes(self, v: &[u8]) -> Result<Self::Ok, Self::Error> {
+        self.nodes(1)?;
         Ok(Value::Bytes(v.to_vec()))
     }
 
     fn serialize_none(self) -> Result<Self::Ok, Self::Error> {
+        self.nodes(1)?;
         Ok(Value::Option(None))
     }
 
-    fn serialize_some<T: ?Sized>(
+    fn serialize_some<T>(
         self,
         value: &T
     ) -> Result<Self::Ok, Self::Error>
     where
-        T: ser::Serialize
+        T: ?Sized + ser::Serialize
     {
-        value.serialize(Serializer).map(|v| Value::Option(Some(Box::new(v))))
+        value.serialize(self.nodes(1)?).map(|v| Value::Option(Some(Box::new(v))))
     }
 
     fn serialize_unit(self) -> Result<Self::Ok, Self::Error> {
+        self.nodes(1)?;
         Ok(Value::Unit)
     }
 
@@ -152,6 +224,7 @@ impl ser::Serializer for Serializer {
         self,
         _name: &'static str
     ) -> Result<Self::Ok, Self::Error> {
+        self.nodes(1)?;
         Ok(Value::Unit)
     }
 
@@ -161,21 +234,22 @@ impl ser::Serializer for Serializer {
         _variant_index: u32,
         variant: &'static str,
     ) -> Result<Self::Ok, Self::Error> {
+        self.nodes(1)?;
         Ok(Value::String(variant.to_string()))
     }
 
-    fn serialize_newtype_struct<T: ?Sized>(
+    fn serialize_newtype_struct<T>(
         self,
         _name: &'static str,
         value: &T
     ) -> Result<Self::Ok, Self::Error>
     where
-        T: ser::Serialize
+        T: ?Sized + ser::Serialize
     {
-        value.serialize(Serializer).map(|v| Value::Newtype(Box::new(v)))
+        value.serialize(self.nodes(1)?).map(|v| Value::Newtype(Box::new(v)))
     }
 
-    fn serialize_newtype_variant<T: ?Sized>(
+    fn serialize_newtype_variant<T>(
         self,
         _name: &'static str,
         _variant_index: u32,
@@ -183,10 +257,10 @@ impl ser::Serializer for Serializer {
         value: &T
     ) -> Result<Self::Ok, Self::Error>
     where
-        T: ser::Serialize
+        T: ?Sized + ser::Serialize
     {
-        value.serialize(Serializer).map(|v| {
-            let mut map = BTreeMap::new();
+        value.serialize(self.nodes(2)?).map(|v| {
+            let mut map = ValueMap::new();
             map.insert(Value::String(variant.to_string()), v);
             Value::Map(map)
         })
@@ -196,14 +270,14 @@ impl ser::Serializer for Serializer {
         self,
         _len: Option<usize>
     ) -> Result<Self::SerializeSeq, Self::Error> {
-        Ok(SerializeSeq(vec![]))
+        Ok(SerializeSeq(self.nodes(1)?, vec![]))
     }
 
     fn serialize_tuple(
         self,
         _len: usize
     ) -> Result<Self::SerializeTuple, Self::Error> {
-        Ok(SerializeTuple(vec![]))
+        Ok(SerializeTuple(self.nodes(1)?, vec![]))
     }
 
     fn serialize_tuple_struct(
@@ -211,7 +285,7 @@ impl ser::Serializer for Serializer {
         _name: &'static str,
         _len: usize
     ) -> Result<Self::SerializeTupleStruct, Self::Error> {
-        Ok(SerializeTupleStruct(vec![]))
+        Ok(SerializeTupleStruct(self.nodes(1)?, vec![]))
     }
 
     fn serialize_tuple_variant(
@@ -222,6 +296,7 @@ impl ser::Serializer for Serializer {
         len: usize
     ) -> Result<Self::SerializeTupleVariant, Self::Error> {
         Ok(SerializeTupleVariant(
+            self.nodes(3)?,
             Value::String(variant.to_string()),
             Vec::with_capacity(len),
         ))
@@ -231,7 +306,7 @@ impl ser::Serializer for Serializer {
         self,
         _len: Option<usize>
     ) -> Result<Self::SerializeMap, Self::Error> {
-        Ok(SerializeMap { map: BTreeMap::new(), key: None })
+        Ok(SerializeMap { ser: self.nodes(1)?, map: ValueMap::new(), key: None })
     }
 
     fn serialize_struct(
@@ -239,7 +314,7 @@ impl ser::Serializer for Serializer {
         _name: &'static str,
         _len: usize
     ) -> Result<Self::SerializeStruct, Self::Error> {
-        Ok(SerializeStruct(BTreeMap::new()))
+        Ok(SerializeStruct(self.nodes(1)?, ValueMap::new()))
     }
 
     fn serialize_struct_variant(
@@ -250,133 +325,136 @@ impl ser::Serializer for Serializer {
         _len: usize
     ) -> Result<Self::SerializeStructVariant, Self::Error> {
         Ok(SerializeStructVariant(
+            self.nodes(3)?,
             Value::String(variant.to_string()),
-            BTreeMap::new(),
+            ValueMap::new(),
         ))
     }
 }
 
-struct SerializeSeq(Vec<Value>);
+struct SerializeSeq<'a>(Serializer<'a>, Vec<Value>);
 
-impl ser::SerializeSeq for SerializeSeq {
+impl<'a> ser::SerializeSeq for SerializeSeq<'a> {
     type Ok = Value;
     type Error = SerializerError;
 
-    fn serialize_element<T: ?Sized>(
+    fn serialize_element<T>(
         &mut self,
         value: &T
     ) -> Result<(), Self::Error>
     where
-        T: ser::Serialize
+        T: ?Sized + ser::Serialize
     {
-        let value = value.serialize(Serializer)?;
-        self.0.push(value);
+        let value = value.serialize(self.0)?;
+        self.1.push(value);
         Ok(())
     }
 
     fn end(self) -> Result<Self::Ok, Self::Error> {
-        Ok(Value::Seq(self.0))
+        Ok(Value::Seq(self.1))
     }
 }
 
-struct SerializeTuple(Vec<Value>);
+struct SerializeTuple<'a>(Serializer<'a>, Vec<Value>);
 
-impl ser::SerializeTuple for SerializeTuple {
+impl<'a> ser::SerializeTuple for SerializeTuple<'a> {
     type Ok = Value;
     type Error = SerializerError;
 
-    fn serialize_element<T: ?Sized>(
+    fn serialize_element<T>(
         &mut self,
         value: &T
     ) -> Result<(), Self::Error>
     where
-        T: ser::Serialize
+        T: ?Sized + ser::Serialize
     {
-        let value = value.serialize(Serializer)?;
-        self.0.push(value);
+        let value = value.serialize(self.0)?;
+        self.1.push(value);
         Ok(())
     }
 
     fn end(self) -> Result<Self::Ok, Self::Error> {
-        Ok(Value::Seq(self.0))
+        Ok(Value::Seq(self.1))
     }
 }
 
-struct SerializeTupleStruct(Vec<Value>);
+struct SerializeTupleStruct<'a>(Serializer<'a>, Vec<Value>);
 
-impl ser::SerializeTupleStruct for SerializeTupleStruct {
+impl<'a> ser::SerializeTupleStruct for SerializeTupleStruct<'a> {
     type Ok = Value;
     type Error = SerializerError;
 
-    fn serialize_field<T: ?Sized>(
+    fn serialize_field<T>(
         &mut self,
         value: &T
     ) -> Result<(), Self::Error>
     where
-        T: ser::Serialize
+        T: ?Sized + ser::Serialize
     {
-        let value = value.serialize(Serializer)?;
-        self.0.push(value);
+        let value = value.serialize(self.0)?;
+        self.1.push(value);
         Ok(())
     }
 
     fn end(self) -> Result<Self::Ok, Self::Error> {
-        Ok(Value::Seq(self.0))
+        Ok(Value::Seq(self.1))
     }
 }
 
-struct SerializeTupleVariant(Value, Vec<Value>);
+struct SerializeTupleVariant<'a>(Serializer<'a>, Value, Vec<Value>);
 
-impl ser::SerializeTupleVariant for SerializeTupleVariant {
+impl<'a> ser::SerializeTupleVariant for SerializeTupleVariant<'a> {
     type Ok = Value;
     type Error = SerializerError;
 
-    fn serialize_field<T: ?Sized>(
+    fn serialize_field<T>(
         &mut self,
         value: &T
     ) -> Result<(), Self::Error>
     where
-        T: ser::Serialize
+        T: ?Sized + ser::Serialize
     {
-        let value = value.serialize(Serializer)?;
-        self.1.push(value);
+        let value = value.serialize(self.0)?;
+        self.2.push(value);
         Ok(())
     }
 
     fn end(self) -> Result<Self::Ok, Self::Error> {
-        let mut map = BTreeMap::new();
-        map.insert(self.0, Value::Seq(self.1));
+        let mut map = ValueMap::new();
+        map.insert(self.1, Value::Seq(self.2));
         Ok(Value::Map(map))
     }
 }
 
-struct SerializeMap {
-    map: BTreeMap<Value, Value>,
+struct SerializeMap<'a> {
+    ser: Serializer<'a>,
+    map: ValueMap,
     key: Option<Value>,
 }
 
-impl ser::SerializeMap for SerializeMap {
+impl<'a> ser::SerializeMap for SerializeMap<'a> {
     type Ok = Value;
     type Error = SerializerError;
 
-    fn serialize_key<T: ?Sized>(&mut self, key: &T) -> Result<(), Self::Error>
+    fn serialize_key<T>(&mut self, key: &T) -> Result<(), Self::Error>
     where
-        T: ser::Serialize
+        T: ?Sized + ser::Serialize
     {
-        let key = key.serialize(Serializer)?;
+        let key = key.serialize(self.ser)?;
         self.key = Some(key);
         Ok(())
     }
 
-    fn serialize_value<T: ?Sized>(
+    fn serialize_value<T>(
         &mut self,
         value: &T
     ) -> Result<(), Self::Error>
     where
-        T: ser::Serialize
+        T: ?Sized + ser::Serialize
     {
-        let value = value.serialize(Serializer)?;
-        self.map.insert(self.key.take().unwrap(), value);
+        let key = self.key.take().ok_or(SerializerError::MissingKey)?;
+        let value = value.serialize(self.ser)?;
+        self.map.insert(key, value);
         Ok(())
     }
 
@@ -385,54 +463,509 @@ impl ser::SerializeMap for SerializeMap {
     }
 }
 
-struct SerializeStruct(BTreeMap<Value, Value>);
+struct SerializeStruct<'a>(Serializer<'a>, ValueMap);
 
-impl ser::SerializeStruct for SerializeStruct {
+impl<'a> ser::SerializeStruct for SerializeStruct<'a> {
     type Ok = Value;
     type Error = SerializerError;
 
-    fn serialize_field<T: ?Sized>(
+    fn serialize_field<T>(
         &mut self,
         key: &'static str,
         value: &T
     ) -> Result<(), Self::Error>
     where
-        T: ser::Serialize
+        T: ?Sized + ser::Serialize
     {
+        self.0.nodes(1)?;
         let key = Value::String(key.to_string());
-        let value = value.serialize(Serializer)?;
-        self.0.insert(key, value);
+        let value = value.serialize(self.0)?;
+        self.1.insert(key, value);
         Ok(())
     }
 
     fn end(self) -> Result<Self::Ok, Self::Error> {
-        Ok(Value::Map(self.0))
+        Ok(map_value(self.1))
     }
 }
 
-struct SerializeStructVariant(Value, BTreeMap<Value, Value>);
+struct SerializeStructVariant<'a>(Serializer<'a>, Value, ValueMap);
 
-impl ser::SerializeStructVariant for SerializeStructVariant {
+impl<'a> ser::SerializeStructVariant for SerializeStructVariant<'a> {
     type Ok = Value;
     type Error = SerializerError;
 
-    fn serialize_field<T: ?Sized>(
+    fn serialize_field<T>(
         &mut self,
         key: &'static str,
         value: &T
     ) -> Result<(), Self::Error>
     where
-        T: ser::Serialize
+        T: ?Sized + ser::Serialize
     {
+        self.0.nodes(1)?;
         let key = Value::String(key.to_string());
-        let value = value.serialize(Serializer)?;
-        self.1.insert(key, value);
+        let value = value.serialize(self.0)?;
+        self.2.insert(key, value);
         Ok(())
     }
 
     fn end(self) -> Result<Self::Ok, Self::Error> {
-        let mut map = BTreeMap::new();
-        map.insert(self.0, Value::Map(self.1));
+        let mut map = ValueMap::new();
+        map.insert(self.1, Value::Map(self.2));
         Ok(Value::Map(map))
     }
 }
+
+/// Serializes the wrapped value with a fixed `is_human_readable` setting,
+/// regardless of what the underlying serializer reports.
+///
+/// The setting is propagated to every nested value, so types that choose a
+/// different representation depending on `is_human_readable` (such as
+/// `std::net::IpAddr`) are serialized consistently.
+pub struct HumanReadable<T> {
+    value: T,
+    human_readable: bool,
+}
+
+impl<T> HumanReadable<T> {
+    pub fn new(value: T, human_readable: bool) -> Self {
+        HumanReadable {
+            value,
+            human_readable,
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value
+    }
+}
+
+impl<T: ser::Serialize> ser::Serialize for HumanReadable<T> {
+    fn serialize<S: ser::Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        self.value.serialize(HumanReadableSerializer {
+            inner: s,
+            human_readable: self.human_readable,
+        })
+    }
+}
+
+impl Value {
+    /// Wraps `self` so that it serializes with `is_human_readable` reporting
+    /// `human_readable` at every level, whatever the serializer itself
+    /// reports. See `HumanReadable`.
+    pub fn serialize_as(&self, human_readable: bool) -> HumanReadable<&Value> {
+        HumanReadable::new(self, human_readable)
+    }
+
+    /// Serializes a `Value::Newtype` as a newtype struct named `name`, for
+    /// formats such as RON that print struct names. `Value::Newtype` does not
+    /// store its original name, so it is otherwise serialized with an empty
+    /// one. Any other value, and any newtype nested within, is serialized
+    /// as usual.
+    pub fn serialize_newtype_as(&self, name: &'static str) -> NamedNewtype<'_> {
+        NamedNewtype {
+            value: self,
+            name,
+        }
+    }
+}
+
+/// Serializes a `Value` with a newtype struct name. See
+/// `Value::serialize_newtype_as`.
+pub struct NamedNewtype<'a> {
+    value: &'a Value,
+    name: &'static str,
+}
+
+impl<'a> ser::Serialize for NamedNewtype<'a> {
+    fn serialize<S: ser::Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        match *self.value {
+            Value::Newtype(ref v) => s.serialize_newtype_struct(self.name, v),
+            ref v => v.serialize(s),
+        }
+    }
+}
+
+struct HumanReadableSerializer<S> {
+    inner: S,
+    human_readable: bool,
+}
+
+impl<S: ser::Serializer> ser::Serializer for HumanReadableSerializer<S> {
+    type Ok = S::Ok;
+    type Error = S::Error;
+    type SerializeSeq = HumanReadableCompound<S::SerializeSeq>;
+    type SerializeTuple = HumanReadableCompound<S::SerializeTuple>;
+    type SerializeTupleStruct = HumanReadableCompound<S::SerializeTupleStruct>;
+    type SerializeTupleVariant = HumanReadableCompound<S::SerializeTupleVariant>;
+    type SerializeMap = HumanReadableCompound<S::SerializeMap>;
+    type SerializeStruct = HumanReadableCompound<S::SerializeStruct>;
+    type SerializeStructVariant = HumanReadableCompound<S::SerializeStructVariant>;
+
+    fn is_human_readable(&self) -> bool {
+        self.human_readable
+    }
+
+    fn serialize_bool(self, v: bool) -> Result<Self::Ok, Self::Error> {
+        self.inner.serialize_bool(v)
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Self::Ok, Self::Error> {
+        self.inner.serialize_i8(v)
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Self::Ok, Self::Error> {
+        self.inner.serialize_i16(v)
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Self::Ok, Self::Error> {
+        self.inner.serialize_i32(v)
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Self::Ok, Self::Error> {
+        self.inner.serialize_i64(v)
+    }
+
+    fn serialize_i128(self, v: i128) -> Result<Self::Ok, Self::Error> {
+        self.inner.serialize_i128(v)
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Self::Ok, Self::Error> {
+        self.inner.serialize_u8(v)
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Self::Ok, Self::Error> {
+        self.inner.serialize_u16(v)
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Self::Ok, Self::Error> {
+        self.inner.serialize_u32(v)
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Self::Ok, Self::Error> {
+        self.inner.serialize_u64(v)
+    }
+
+    fn serialize_u128(self, v: u128) -> Result<Self::Ok, Self::Error> {
+        self.inner.serialize_u128(v)
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<Self::Ok, Self::Error> {
+        self.inner.serialize_f32(v)
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<Self::Ok, Self::Error> {
+        self.inner.serialize_f64(v)
+    }
+
+    fn serialize_char(self, v: char) -> Result<Self::Ok, Self::Error> {
+        self.inner.serialize_char(v)
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Self::Ok, Self::Error> {
+        self.inner.serialize_str(v)
+    }
+
+    fn collect_str<T: ?Sized + fmt::Display>(self, value: &T) -> Result<Self::Ok, Self::Error> {
+        self.inner.collect_str(value)
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Self::Ok, Self::Error> {
+        self.inner.serialize_bytes(v)
+    }
+
+    fn serialize_none(self) -> Result<Self::Ok, Self::Error> {
+        self.inner.serialize_none()
+    }
+
+    fn serialize_some<T>(
+        self,
+        value: &T
+    ) -> Result<Self::Ok, Self::Error>
+    where
+        T: ?Sized + ser::Serialize
+    {
+        self.inner.serialize_some(&HumanReadable::new(value, self.human_readable))
+    }
+
+    fn serialize_unit(self) -> Result<Self::Ok, Self::Error> {
+        self.inner.serialize_unit()
+    }
+
+    fn serialize_unit_struct(
+        self,
+        name: &'static str
+    ) -> Result<Self::Ok, Self::Error> {
+        self.inner.serialize_unit_struct(name)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+    ) -> Result<Self::Ok, Self::Error> {
+        self.inner.serialize_unit_variant(name, variant_index, variant)
+    }
+
+    fn serialize_newtype_struct<T>(
+        self,
+        name: &'static str,
+        value: &T
+    ) -> Result<Self::Ok, Self::Error>
+    where
+        T: ?Sized + ser::Serialize
+    {
+        self.inner.serialize_newtype_struct(name, &HumanReadable::new(value, self.human_readable))
+    }
+
+    fn serialize_newtype_variant<T>(
+        self,
+        name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+        value: &T
+    ) -> Result<Self::Ok, Self::Error>
+    where
+        T: ?Sized + ser::Serialize
+    {
+        let value = HumanReadable::new(value, self.human_readable);
+        self.inner.serialize_newtype_variant(name, variant_index, variant, &value)
+    }
+
+    fn serialize_seq(
+        self,
+        len: Option<usize>
+    ) -> Result<Self::SerializeSeq, Self::Error> {
+        let human_readable = self.human_readable;
+        self.inner.serialize_seq(len).map(|inner| HumanReadableCompound { inner, human_readable })
+    }
+
+    fn serialize_tuple(
+        self,
+        len: usize
+    ) -> Result<Self::SerializeTuple, Self::Error> {
+        let human_readable = self.human_readable;
+        self.inner.serialize_tuple(len).map(|inner| HumanReadableCompound { inner, human_readable })
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        name: &'static str,
+        len: usize
+    ) -> Result<Self::SerializeTupleStruct, Self::Error> {
+        let human_readable = self.human_readable;
+        self.inner.serialize_tuple_struct(name, len).map(|inner| HumanReadableCompound { inner, human_readable })
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+        len: usize
+    ) -> Result<Self::SerializeTupleVariant, Self::Error> {
+        let human_readable = self.human_readable;
+        self.inner.serialize_tuple_variant(name, variant_index, variant, len)
+            .map(|inner| HumanReadableCompound { inner, human_readable })
+    }
+
+    fn serialize_map(
+        self,
+        len: Option<usize>
+    ) -> Result<Self::SerializeMap, Self::Error> {
+        let human_readable = self.human_readable;
+        self.inner.serialize_map(len).map(|inner| HumanReadableCompound { inner, human_readable })
+    }
+
+    fn serialize_struct(
+        self,
+        name: &'static str,
+        len: usize
+    ) -> Result<Self::SerializeStruct, Self::Error> {
+        let human_readable = self.human_readable;
+        self.inner.serialize_struct(name, len).map(|inner| HumanReadableCompound { inner, human_readable })
+    }
+
+    fn serialize_struct_variant(
+        self,
+        name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+        len: usize
+    ) -> Result<Self::SerializeStructVariant, Self::Error> {
+        let human_readable = self.human_readable;
+        self.inner.serialize_struct_variant(name, variant_index, variant, len)
+            .map(|inner| HumanReadableCompound { inner, human_readable })
+    }
+}
+
+struct HumanReadableCompound<C> {
+    inner: C,
+    human_readable: bool,
+}
+
+impl<C> HumanReadableCompound<C> {
+    fn wrap<'a, T: ?Sized>(&self, value: &'a T) -> HumanReadable<&'a T> {
+        HumanReadable::new(value, self.human_readable)
+    }
+}
+
+impl<C: ser::SerializeSeq> ser::SerializeSeq for HumanReadableCompound<C> {
+    type Ok = C::Ok;
+    type Error = C::Error;
+
+    fn serialize_element<T>(
+        &mut self,
+        value: &T
+    ) -> Result<(), Self::Error>
+    where
+        T: ?Sized + ser::Serialize
+    {
+        let value = self.wrap(value);
+        self.inner.serialize_element(&value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        self.inner.end()
+    }
+}
+
+impl<C: ser::SerializeTuple> ser::SerializeTuple for HumanReadableCompound<C> {
+    type Ok = C::Ok;
+    type Error = C::Error;
+
+    fn serialize_element<T>(
+        &mut self,
+        value: &T
+    ) -> Result<(), Self::Error>
+    where
+        T: ?Sized + ser::Serialize
+    {
+        let value = self.wrap(value);
+        self.inner.serialize_element(&value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        self.inner.end()
+    }
+}
+
+impl<C: ser::SerializeTupleStruct> ser::SerializeTupleStruct for HumanReadableCompound<C> {
+    type Ok = C::Ok;
+    type Error = C::Error;
+
+    fn serialize_field<T>(
+        &mut self,
+        value: &T
+    ) -> Result<(), Self::Error>
+    where
+        T: ?Sized + ser::Serialize
+    {
+        let value = self.wrap(value);
+        self.inner.serialize_field(&value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        self.inner.end()
+    }
+}
+
+impl<C: ser::SerializeTupleVariant> ser::SerializeTupleVariant for HumanReadableCompound<C> {
+    type Ok = C::Ok;
+    type Error = C::Error;
+
+    fn serialize_field<T>(
+        &mut self,
+        value: &T
+    ) -> Result<(), Self::Error>
+    where
+        T: ?Sized + ser::Serialize
+    {
+        let value = self.wrap(value);
+        self.inner.serialize_field(&value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        self.inner.end()
+    }
+}
+
+impl<C: ser::SerializeMap> ser::SerializeMap for HumanReadableCompound<C> {
+    type Ok = C::Ok;
+    type Error = C::Error;
+
+    fn serialize_key<T>(&mut self, key: &T) -> Result<(), Self::Error>
+    where
+        T: ?Sized + ser::Serialize
+    {
+        let key = self.wrap(key);
+        self.inner.serialize_key(&key)
+    }
+
+    fn serialize_value<T>(
+        &mut self,
+        value: &T
+    ) -> Result<(), Self::Error>
+    where
+        T: ?Sized + ser::Serialize
+    {
+        let value = self.wrap(value);
+        self.inner.serialize_value(&value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        self.inner.end()
+    }
+}
+
+impl<C: ser::SerializeStruct> ser::SerializeStruct for HumanReadableCompound<C> {
+    type Ok = C::Ok;
+    type Error = C::Error;
+
+    fn serialize_field<T>(
+        &mut self,
+        key: &'static str,
+        value: &T
+    ) -> Result<(), Self::Error>
+    where
+        T: ?Sized + ser::Serialize
+    {
+        let value = self.wrap(value);
+        self.inner.serialize_field(key, &value)
+    }
+
+    fn skip_field(&mut self, key: &'static str) -> Result<(), Self::Error> {
+        self.inner.skip_field(key)
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        self.inner.end()
+    }
+}
+
+impl<C: ser::SerializeStructVariant> ser::SerializeStructVariant for HumanReadableCompound<C> {
+    type Ok = C::Ok;
+    type Error = C::Error;
+
+    fn serialize_field<T>(
+        &mut self,
+        key: &'static str,
+        value: &T
+    ) -> Result<(), Self::Error>
+    where
+        T: ?Sized + ser::Serialize
+    {
+        let value = self.wrap(value);
+        self.inner.serialize_field(key, &value)
+    }
+
+    fn skip_field(&mut self, key: &'static str) -> Result<(), Self::Error> {
+        self.inner.skip_field(key)
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        self.inner.end()
+    }
+}
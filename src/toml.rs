@@ -0,0 +1,68 @@
+//! Conversions between `Value` and `toml::Value`.
+//!
+//! TOML datetimes have no equivalent `Value` variant and are converted to a
+//! `Value::String` in RFC 3339 form. Converting back produces a TOML string,
+//! not a datetime.
+//!
+//! TOML tables only support string keys, and TOML has no null, so converting
+//! a `Value` fails on a map with a non-`String` key or on any `Value::Unit`
+//! or `Value::Option(None)`. `Value::Option(Some(..))` and `Value::Newtype`
+//! wrappers are unwrapped, `Value::Char` becomes a string and `Value::Bytes`
+//! becomes an array of integers. `Value::BigInt` cannot be converted.
+
+use std::convert::TryFrom;
+use serde::ser::Error;
+
+use crate::{SerializerError, Value};
+
+impl From<::toml::Value> for Value {
+    fn from(value: ::toml::Value) -> Value {
+        match value {
+            ::toml::Value::String(v) => Value::String(v),
+            ::toml::Value::Integer(v) => Value::I64(v),
+            ::toml::Value::Float(v) => Value::F64(v),
+            ::toml::Value::Boolean(v) => Value::Bool(v),
+            ::toml::Value::Datetime(v) => Value::String(v.to_string()),
+            ::toml::Value::Array(v) => Value::Seq(v.into_iter().map(Value::from).collect()),
+            ::toml::Value::Table(v) => Value::Map(v.into_iter().map(|(k, v)| (Value::String(k), v.into())).collect()),
+        }
+    }
+}
+
+impl TryFrom<Value> for ::toml::Value {
+    type Error = SerializerError;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        to_toml(value)
+    }
+}
+
+fn to_toml(value: Value) -> Result<::toml::Value, SerializerError> {
+    Ok(match value {
+        Value::Bool(v) => ::toml::Value::Boolean(v),
+        Value::U8(v) => ::toml::Value::Integer(v.into()),
+        Value::U16(v) => ::toml::Value::Integer(v.into()),
+        Value::U32(v) => ::toml::Value::Integer(v.into()),
+        Value::U64(v) => match i64::try_from(v) {
+            Ok(v) => ::toml::Value::Integer(v),
+            Err(..) => return Err(SerializerError::custom(format_args!("integer {} out of range for TOML", v))),
+        },
+        Value::I8(v) => ::toml::Value::Integer(v.into()),
+        Value::I16(v) => ::toml::Value::Integer(v.into()),
+        Value::I32(v) => ::toml::Value::Integer(v.into()),
+        Value::I64(v) => ::toml::Value::Integer(v),
+        Value::F32(v) => ::toml::Value::Float(v.into()),
+        Value::F64(v) => ::toml::Value::Float(v),
+        Value::Char(v) => ::toml::Value::String(v.to_string()),
+        Value::String(v) => ::toml::Value::String(v),
+        Value::Unit | Value::Option(None) => return Err(SerializerError::custom("TOML does not support null values")),
+        Value::Option(Some(v)) | Value::Newtype(v) => to_toml(*v)?,
+        Value::Seq(v) => ::toml::Value::Array(v.into_iter().map(to_toml).collect::<Result<_, _>>()?),
+        Value::Map(v) => ::toml::Value::Table(v.into_iter().map(|(k, v)| match k {
+            Value::String(k) => to_toml(v).map(|v| (k, v)),
+            k => Err(SerializerError::KeyMustBeString(k)),
+        }).collect::<Result<_, _>>()?),
+        Value::Bytes(v) => ::toml::Value::Array(v.into_iter().map(|v| ::toml::Value::Integer(v.into())).collect()),
+        Value::BigInt(..) => return Err(SerializerError::custom("TOML does not support big integers")),
+    })
+}